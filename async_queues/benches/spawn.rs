@@ -0,0 +1,180 @@
+use std::time::{Duration, Instant};
+
+use async_queues::{FutureType, Runtime, join_all, spawn_task};
+
+// Spawn-and-run throughput of empty tasks, i.e. the spawn -> schedule -> run hot path with
+// nothing else in it, measured for the runtime as it was before any of its features (the
+// baseline executor below) and for the current one:
+//
+//     cargo bench --bench spawn
+//     cargo bench --bench spawn --features metrics,task-registry
+//     cargo bench --bench spawn --features scheduler-log
+//
+// The task registry, the metrics and the scheduler log are compiled in with their features
+// only. Without them, what a task still pays for over the baseline is the rest of the runtime:
+// a bigger task (its record, and the wrapper that counts its depth and carries its log
+// context), the routing, depth, inline-poll, capacity and continuation checks on spawn, waking
+// a parked worker, and the busy count and panic policy around every poll. Settings that are
+// off by default (order recording, the slow-poll watchdog, TTLs, the coop budget, layers) cost
+// one load each. The report prints the difference against the baseline, and with no features
+// the run fails if it is over BUDGET. With scheduler-log, the log is measured both without a
+// sink (the cost of having it compiled in) and with a sink that drops every event.
+
+const TASKS: usize = 100_000;
+const ROUNDS: usize = 7;
+const HIGH_WORKERS: usize = 2;
+const LOW_WORKERS: usize = 1;
+// Most the features-off runtime may take per task, as a multiple of the baseline's time. It
+// measures at about 2x on a single core, where the bigger task weighs the most.
+const BUDGET: f64 = 2.5;
+
+// The executor the crate started out as: two flume channels, high workers probing high then
+// low, low workers low then high, a bare async_task spawn and nothing else. The one change is
+// that idle workers yield instead of napping 100ms, so both sides are measured on the hot path
+// rather than on how long the baseline took to notice new work. They sleep while the current
+// runtime is measured.
+mod baseline {
+    use std::future::Future;
+    use std::sync::LazyLock;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use async_task::{Runnable, Task};
+    use flume::{Receiver, Sender};
+
+    use super::{HIGH_WORKERS, LOW_WORKERS};
+
+    type Channel = (Sender<Runnable>, Receiver<Runnable>);
+
+    static HIGH: LazyLock<Channel> = LazyLock::new(flume::unbounded);
+    static LOW: LazyLock<Channel> = LazyLock::new(flume::unbounded);
+    // set while the current runtime is measured, so the spinning workers leave it the CPUs
+    static PAUSED: AtomicBool = AtomicBool::new(true);
+
+    pub fn start() {
+        for (workers, first, second) in [(HIGH_WORKERS, &HIGH, &LOW), (LOW_WORKERS, &LOW, &HIGH)] {
+            for _ in 0..workers {
+                thread::spawn(move || {
+                    loop {
+                        if PAUSED.load(Ordering::Relaxed) {
+                            thread::sleep(Duration::from_millis(1));
+                            continue;
+                        }
+                        match first.1.try_recv().or_else(|_| second.1.try_recv()) {
+                            Ok(runnable) => {
+                                runnable.run();
+                            }
+                            Err(_) => thread::yield_now(),
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    pub fn set_paused(paused: bool) {
+        PAUSED.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn spawn_high<F, T>(future: F) -> Task<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (runnable, task) = async_task::spawn(future, |runnable| HIGH.0.send(runnable).unwrap());
+        runnable.schedule();
+        task
+    }
+}
+
+fn baseline_round() -> Duration {
+    baseline::set_paused(false);
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..TASKS).map(|_| baseline::spawn_high(async {})).collect();
+    futures_lite::future::block_on(join_all(tasks));
+    let elapsed = start.elapsed();
+    baseline::set_paused(true);
+    elapsed
+}
+
+fn runtime_round(runtime: &Runtime) -> Duration {
+    runtime.run();
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|_| spawn_task(async {}, FutureType::High))
+        .collect();
+    runtime.block_on(join_all(tasks));
+    start.elapsed()
+}
+
+fn report(label: &str, best: Duration) {
+    let per_task = best / TASKS as u32;
+    let throughput = TASKS as f64 / best.as_secs_f64();
+    println!("{label:<26} {per_task:>10?}/task {throughput:>12.0} tasks/s");
+}
+
+fn main() {
+    baseline::start();
+    let config = || {
+        Runtime::new()
+            .with_high_num(HIGH_WORKERS)
+            .with_low_num(LOW_WORKERS)
+    };
+    let features = ["metrics", "scheduler-log", "task-registry"]
+        .into_iter()
+        .zip([
+            cfg!(feature = "metrics"),
+            cfg!(feature = "scheduler-log"),
+            cfg!(feature = "task-registry"),
+        ])
+        .filter_map(|(feature, on)| on.then_some(feature))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let features_off = features.is_empty();
+    let label = if features_off {
+        "current, features off".to_string()
+    } else {
+        features
+    };
+    #[cfg(not(feature = "scheduler-log"))]
+    let variants = [(label, config())];
+    #[cfg(feature = "scheduler-log")]
+    let variants = [
+        (format!("{label}, no sink"), config()),
+        (
+            format!("{label}, no-op sink"),
+            config().with_scheduler_log(|_| {}),
+        ),
+    ];
+
+    // best of ROUNDS for each, after one round to get the workers going. The rounds take turns
+    // so a slower stretch of the machine doesn't land on one side only.
+    let mut baseline = Duration::MAX;
+    let mut best = vec![Duration::MAX; variants.len()];
+    for round in 0..=ROUNDS {
+        let took = baseline_round();
+        if round > 0 {
+            baseline = baseline.min(took);
+        }
+        for ((_, runtime), best) in variants.iter().zip(&mut best) {
+            let took = runtime_round(runtime);
+            if round > 0 {
+                *best = (*best).min(took);
+            }
+        }
+    }
+
+    report("baseline executor", baseline);
+    let mut over_budget = false;
+    for ((label, _), best) in variants.iter().zip(best) {
+        report(label, best);
+        let ratio = best.as_secs_f64() / baseline.as_secs_f64();
+        println!("{:<26} {:>+9.0}% vs baseline", "", (ratio - 1.0) * 100.0);
+        over_budget |= features_off && ratio > BUDGET;
+    }
+    if over_budget {
+        eprintln!("features off, the runtime is over its budget of {BUDGET}x the baseline");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+// Exponential backoff shared by RestartPolicy and RetryPolicy: `initial` before the first
+// repeat, doubled for every further one and capped at `max`. The default never waits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Backoff {
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max: max.max(initial),
+        }
+    }
+
+    // How long to wait before the `nth` repeat, counting from 1
+    pub(crate) fn delay(&self, nth: u32) -> Duration {
+        let factor = 1u32.checked_shl(nth.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::{FutureType, rng};
+
+// Which queue a worker probes first on each pass; it falls back to the other one when the
+// first is empty. The defaults keep each pool on its own queue first: high workers go
+// high-then-low, low workers low-then-high.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorkerBias {
+    HighFirst,
+    LowFirst,
+    // probe the high queue first with this probability (0.0..=1.0), the low queue otherwise,
+    // e.g. `Weighted { high: 0.1 }` for low workers that occasionally help out with high work
+    Weighted { high: f64 },
+}
+
+impl WorkerBias {
+    pub fn default_for(pool: FutureType) -> Self {
+        match pool {
+            FutureType::High => Self::HighFirst,
+            FutureType::Low => Self::LowFirst,
+        }
+    }
+
+    fn high_probability(self) -> f64 {
+        match self {
+            Self::HighFirst => 1.0,
+            Self::LowFirst => 0.0,
+            Self::Weighted { high } => high.clamp(0.0, 1.0),
+        }
+    }
+}
+
+// Stored as the f64 bits of the probability of probing the high queue first
+static HIGH_POOL: AtomicU64 = AtomicU64::new(0x3FF0_0000_0000_0000); // 1.0
+static LOW_POOL: AtomicU64 = AtomicU64::new(0);
+
+fn slot(pool: FutureType) -> &'static AtomicU64 {
+    match pool {
+        FutureType::High => &HIGH_POOL,
+        FutureType::Low => &LOW_POOL,
+    }
+}
+
+pub(crate) fn set(pool: FutureType, bias: WorkerBias) {
+    slot(pool).store(bias.high_probability().to_bits(), Ordering::Relaxed);
+}
+
+// Off through Runtime::with_stealing(false): every worker only ever takes from its own pool's
+// queue, and the biases and weights don't apply
+static STEALING: AtomicBool = AtomicBool::new(true);
+
+pub(crate) fn set_stealing(stealing: bool) {
+    STEALING.store(stealing, Ordering::Relaxed);
+}
+
+pub(crate) fn stealing() -> bool {
+    STEALING.load(Ordering::Relaxed)
+}
+
+// Weighted fair queueing across both pools, set through Runtime::with_weights: high weight in
+// the upper half, low weight in the lower half, 0 while off. Takes precedence over the biases.
+static WEIGHTS: AtomicU64 = AtomicU64::new(0);
+// Picks made under the weights so far, by every worker together
+static TURN: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn set_weights(weights: Option<(u32, u32)>) {
+    let packed = match weights {
+        Some((high, low)) if high as u64 + low as u64 > 0 => (high as u64) << 32 | low as u64,
+        _ => 0,
+    };
+    WEIGHTS.store(packed, Ordering::Relaxed);
+}
+
+// Weighted round robin: out of every high + low picks, the first `high` go to the high queue.
+// The turn is shared so the ratio holds across all workers, not just within each one.
+fn weighted_queue(packed: u64) -> FutureType {
+    let (high, low) = (packed >> 32, packed & 0xFFFF_FFFF);
+    let turn = TURN.fetch_add(1, Ordering::Relaxed) % (high + low);
+    if turn < high {
+        FutureType::High
+    } else {
+        FutureType::Low
+    }
+}
+
+pub(crate) fn first_queue(pool: FutureType) -> FutureType {
+    let weights = WEIGHTS.load(Ordering::Relaxed);
+    if weights != 0 {
+        return weighted_queue(weights);
+    }
+    let high = f64::from_bits(slot(pool).load(Ordering::Relaxed));
+    let pick_high = if high >= 1.0 {
+        true
+    } else if high <= 0.0 {
+        false
+    } else {
+        rng::f64() < high
+    };
+    if pick_high {
+        FutureType::High
+    } else {
+        FutureType::Low
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{Runtime, join_all, spawn_task};
+
+    // Queues a task per entry of `orders` before any worker runs, then starts the workers of
+    // `config` and returns the priorities in the order they ran the tasks
+    fn run_order(orders: &[FutureType], config: Runtime) -> Vec<FutureType> {
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let tasks: Vec<_> = orders
+            .iter()
+            .map(|&order| spawn_task(async move { order }, order))
+            .collect();
+        let ids: Vec<_> = tasks.iter().map(|task| task.metadata().id()).collect();
+        Runtime::graceful_restart(config.with_order_recording(true));
+        let orders = futures_lite::future::block_on(join_all(tasks));
+        Runtime::take_execution_order()
+            .into_iter()
+            .map(|id| orders[ids.iter().position(|&spawned| spawned == id).unwrap()])
+            .collect()
+    }
+
+    // Five low and five high tasks on a single low worker with `bias`
+    fn low_worker_order(bias: WorkerBias) -> Vec<FutureType> {
+        let orders = [[FutureType::Low; 5], [FutureType::High; 5]].concat();
+        let config = Runtime::new()
+            .with_high_num(0)
+            .with_low_num(1)
+            .with_worker_bias(FutureType::Low, bias);
+        run_order(&orders, config)
+    }
+
+    // Alternating high and low tasks on a single high worker flipping a seeded coin
+    fn seeded_order(seed: u64) -> Vec<FutureType> {
+        let orders = [FutureType::High, FutureType::Low].repeat(8);
+        let config = Runtime::new()
+            .with_high_num(1)
+            .with_low_num(0)
+            .with_worker_bias(FutureType::High, WorkerBias::Weighted { high: 0.5 })
+            .with_rng_seed(seed);
+        run_order(&orders, config)
+    }
+
+    #[test]
+    fn bias_decides_which_queue_a_worker_drains_first() {
+        let (high, low) = ([FutureType::High; 5], [FutureType::Low; 5]);
+        assert_eq!(
+            low_worker_order(WorkerBias::default_for(FutureType::Low)),
+            [low, high].concat()
+        );
+        assert_eq!(
+            low_worker_order(WorkerBias::HighFirst),
+            [high, low].concat()
+        );
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_execution_order() {
+        let first = seeded_order(42);
+        assert_eq!(first.len(), 16);
+        // the draws actually decided something: it didn't just drain one queue, then the other
+        assert_ne!(first[..8], [FutureType::High; 8]);
+        assert_ne!(first[..8], [FutureType::Low; 8]);
+        assert_eq!(seeded_order(42), first);
+    }
+
+    #[test]
+    fn weights_set_the_high_to_low_ratio() {
+        let orders = [[FutureType::High; 20], [FutureType::Low; 20]].concat();
+        let config = Runtime::new()
+            .with_high_num(1)
+            .with_low_num(0)
+            .with_weights(3, 1);
+        let order = run_order(&orders, config);
+        // while both queues have work, any four picks in a row take three high tasks and one low
+        for window in order[..24].windows(4) {
+            let high = window
+                .iter()
+                .filter(|&&order| order == FutureType::High)
+                .count();
+            assert_eq!(high, 3, "{order:?}");
+        }
+        // the high queue ran dry first, with the low one still at least half full
+        let last_high = order.iter().rposition(|&order| order == FutureType::High);
+        assert!(last_high < Some(30), "{order:?}");
+    }
+}
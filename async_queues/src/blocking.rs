@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use flume::Sender;
+
+// Threads for work that blocks, kept off the async workers; stream_from_iter runs its
+// iterators here. Threads are started on demand, at most MAX_THREADS of them, and exit after
+// IDLE_TIMEOUT without work. Past the cap, jobs wait in a backlog until a thread frees up.
+pub(crate) const MAX_THREADS: usize = 64;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Pool {
+    threads: usize,
+    // one hand-off slot per thread waiting for work. The one that has waited the least gets
+    // the next job, so the others can run into their timeout when there is little to do.
+    idle: Vec<Sender<Job>>,
+    backlog: VecDeque<Job>,
+}
+
+static POOL: Mutex<Pool> = Mutex::new(Pool {
+    threads: 0,
+    idle: Vec::new(),
+    backlog: VecDeque::new(),
+});
+
+pub(crate) fn spawn(job: impl FnOnce() + Send + 'static) {
+    let job: Job = Box::new(job);
+    let mut pool = POOL.lock().unwrap();
+    // sent under the lock, so a thread that timed out and can't find its slot any more knows
+    // the job is already waiting in it
+    if let Some(slot) = pool.idle.pop() {
+        slot.send(job).unwrap();
+    } else if pool.threads < MAX_THREADS {
+        pool.threads += 1;
+        thread::spawn(move || run(job));
+    } else {
+        pool.backlog.push_back(job);
+    }
+}
+
+fn run(mut job: Job) {
+    loop {
+        // a panicking job takes down neither the thread nor its place under the cap
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+        let mut pool = POOL.lock().unwrap();
+        if let Some(next) = pool.backlog.pop_front() {
+            job = next;
+            continue;
+        }
+        let (slot, receiver) = flume::bounded(1);
+        pool.idle.push(slot.clone());
+        drop(pool);
+        job = match receiver.recv_timeout(IDLE_TIMEOUT) {
+            Ok(next) => next,
+            Err(_) => {
+                let mut pool = POOL.lock().unwrap();
+                match pool.idle.iter().position(|idle| idle.same_channel(&slot)) {
+                    Some(index) => {
+                        pool.idle.remove(index);
+                        pool.threads -= 1;
+                        return;
+                    }
+                    None => receiver.recv().unwrap(),
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+
+    use super::*;
+    use crate::Runtime;
+    use crate::test_support::runtime;
+
+    #[test]
+    fn finished_threads_take_the_next_job() {
+        // stream_from_iter tests use the pool too
+        let _runtime = runtime(Runtime::new());
+        let (ids, received) = flume::unbounded();
+        let job = move || ids.send(thread::current().id()).unwrap();
+        spawn(job.clone());
+        let first = received.recv().unwrap();
+        // done, and waiting for work by now
+        thread::sleep(Duration::from_millis(50));
+        spawn(job);
+        assert_eq!(received.recv().unwrap(), first);
+    }
+
+    #[test]
+    fn jobs_past_the_cap_wait_for_a_thread() {
+        let _runtime = runtime(Runtime::new());
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        // the first MAX_THREADS jobs hold on to their threads until all of them run
+        let release = Arc::new(Barrier::new(MAX_THREADS + 1));
+        let (done, finished) = flume::unbounded();
+        for job in 0..MAX_THREADS + 8 {
+            let (running, most, release, done) =
+                (running.clone(), most.clone(), release.clone(), done.clone());
+            spawn(move || {
+                most.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                if job < MAX_THREADS {
+                    release.wait();
+                }
+                running.fetch_sub(1, Ordering::SeqCst);
+                done.send(()).unwrap();
+            });
+        }
+        release.wait();
+        for _ in 0..MAX_THREADS + 8 {
+            finished.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        assert_eq!(most.load(Ordering::SeqCst), MAX_THREADS);
+    }
+}
@@ -0,0 +1,212 @@
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use pin_project_lite::pin_project;
+
+use crate::{FutureType, Task};
+
+// Cloneable flag that can be flipped once; everything awaiting `cancelled()` is woken when
+// it is. Clones share the same flag.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+        for waker in self.inner.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled { token: self }
+    }
+
+    fn poll_cancelled(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_cancelled() {
+            return Poll::Ready(());
+        }
+        let mut wakers = self.inner.wakers.lock().unwrap();
+        // checked again under the lock, cancel() drains the wakers while holding it
+        if self.is_cancelled() {
+            return Poll::Ready(());
+        }
+        if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+// Resolves once the token is cancelled
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.token.poll_cancelled(cx)
+    }
+}
+
+pin_project! {
+    pub struct WithCancellation<F> {
+        #[pin]
+        future: F,
+        token: CancellationToken,
+    }
+}
+
+// Race `future` against `token`: Some(value) if the future finishes first, None once the
+// token is cancelled. The future is checked first, so one that is already done still wins.
+pub fn with_cancellation<F: Future>(future: F, token: CancellationToken) -> WithCancellation<F> {
+    WithCancellation { future, token }
+}
+
+impl<F: Future> Future for WithCancellation<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(value) = this.future.poll(cx) {
+            return Poll::Ready(Some(value));
+        }
+        this.token.poll_cancelled(cx).map(|()| None)
+    }
+}
+
+// Stops the task spawned alongside it by spawn_cancellable, when `cancel` is called or when
+// the handle is dropped, whichever comes first
+pub struct CancelHandle {
+    token: CancellationToken,
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
+impl Drop for CancelHandle {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+// Spawn a future you were handed as is, keeping a separate handle to stop it. The task
+// resolves to None once cancelled; the future is dropped at its next wake-up rather than
+// mid-poll. Hold on to the CancelHandle for as long as the task should run: dropping it
+// cancels too, so `let (task, _) = ...` stops the task right away.
+#[must_use = "dropping the CancelHandle cancels the task"]
+#[track_caller]
+pub fn spawn_cancellable<F, T>(future: F, order: FutureType) -> (Task<Option<T>>, CancelHandle)
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let token = CancellationToken::new();
+    let task = crate::spawn(
+        with_cancellation(future, token.clone()),
+        order,
+        None,
+        Location::caller(),
+    );
+    (task, CancelHandle { token })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::testing::BackgroundFuture;
+    use crate::timer::sleep;
+    use crate::{Runtime, spawn_task};
+
+    #[test]
+    fn token_cancelled_before_the_future_finishes_gives_none() {
+        let _runtime = runtime(Runtime::new());
+        let token = CancellationToken::new();
+        let slow = async {
+            sleep(Duration::from_secs(5)).await;
+            "too late"
+        };
+        let task = spawn_task(with_cancellation(slow, token.clone()), FutureType::Low);
+        let canceller = spawn_task(
+            async move {
+                sleep(Duration::from_millis(20)).await;
+                token.cancel();
+            },
+            FutureType::High,
+        );
+        futures_lite::future::block_on(canceller);
+        assert_eq!(futures_lite::future::block_on(task), None);
+    }
+
+    #[test]
+    fn future_finishing_first_gives_its_value() {
+        let _runtime = runtime(Runtime::new());
+        let token = CancellationToken::new();
+        let task = spawn_task(
+            with_cancellation(async { 7 }, token.clone()),
+            FutureType::Low,
+        );
+        assert_eq!(futures_lite::future::block_on(task), Some(7));
+        token.cancel();
+    }
+
+    #[test]
+    fn cancel_handle_stops_a_background_task() {
+        let _runtime = runtime(Runtime::new());
+        for drop_handle in [false, true] {
+            let background = BackgroundFuture::new();
+            let polls = background.polls();
+            let (task, handle) = spawn_cancellable(background, FutureType::Low);
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while polls.load(Ordering::Acquire) < 10 {
+                assert!(Instant::now() < deadline, "the background task never ran");
+                thread::sleep(Duration::from_millis(1));
+            }
+            if drop_handle {
+                drop(handle);
+            } else {
+                handle.cancel();
+                assert!(handle.is_cancelled());
+            }
+            assert_eq!(futures_lite::future::block_on(task), None);
+            // the future was dropped, nothing polls it any more
+            let stopped = polls.load(Ordering::Acquire);
+            thread::sleep(Duration::from_millis(20));
+            assert_eq!(polls.load(Ordering::Acquire), stopped);
+        }
+    }
+}
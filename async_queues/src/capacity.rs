@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::FutureType;
+
+// Soft per-queue bounds, set through Runtime::with_queue_capacity. The queues themselves stay
+// unbounded, so a spawn never blocks or fails on a full queue; it only reports it to the
+// callback and is queued anyway. usize::MAX means no bound.
+static HIGH_CAPACITY: AtomicUsize = AtomicUsize::new(usize::MAX);
+static LOW_CAPACITY: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+pub(crate) type QueueFullCallback = Arc<dyn Fn(FutureType) + Send + Sync>;
+
+static CALLBACK: RwLock<Option<QueueFullCallback>> = RwLock::new(None);
+
+fn slot(queue: FutureType) -> &'static AtomicUsize {
+    match queue {
+        FutureType::High => &HIGH_CAPACITY,
+        FutureType::Low => &LOW_CAPACITY,
+    }
+}
+
+pub(crate) fn set_capacity(queue: FutureType, capacity: Option<usize>) {
+    slot(queue).store(capacity.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+pub(crate) fn set_callback(callback: Option<QueueFullCallback>) {
+    *CALLBACK.write().unwrap() = callback;
+}
+
+// How many tasks wait in `queue` if that is at or over its capacity
+pub(crate) fn over(queue: FutureType) -> Option<usize> {
+    let capacity = slot(queue).load(Ordering::Relaxed);
+    let queued = crate::queued(queue);
+    (capacity != usize::MAX && queued >= capacity).then_some(queued)
+}
+
+// Called for every spawn before the task is queued on `queue`
+pub(crate) fn check(queue: FutureType) {
+    let capacity = slot(queue).load(Ordering::Relaxed);
+    if capacity == usize::MAX {
+        return;
+    }
+    if crate::queued(queue) < capacity {
+        return;
+    }
+    if let Some(callback) = CALLBACK.read().unwrap().as_ref() {
+        callback(queue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{Runtime, join_all, spawn_task};
+
+    #[test]
+    fn callback_fires_for_spawns_onto_the_full_queue() {
+        let full = Arc::new(Mutex::new(Vec::new()));
+        // no workers, so everything spawned stays queued
+        let _runtime = runtime(
+            Runtime::new()
+                .with_high_num(0)
+                .with_low_num(0)
+                .with_queue_capacity(FutureType::High, 2)
+                .with_queue_full_callback({
+                    let full = full.clone();
+                    move |queue| full.lock().unwrap().push(queue)
+                }),
+        );
+        let mut tasks: Vec<_> = (0..4)
+            .map(|i| spawn_task(async move { i }, FutureType::High))
+            .collect();
+        // the low queue has no capacity set
+        tasks.extend((4..8).map(|i| spawn_task(async move { i }, FutureType::Low)));
+        // the third and fourth found two already waiting
+        assert_eq!(*full.lock().unwrap(), [FutureType::High; 2]);
+        assert_eq!(over(FutureType::High), Some(4));
+        assert_eq!(over(FutureType::Low), None);
+
+        Runtime::graceful_restart(Runtime::new());
+        let outputs = futures_lite::future::block_on(join_all(tasks));
+        assert_eq!(outputs, (0..8).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,150 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::{FutureType, TaskId, TaskRunnable};
+
+// Set through Runtime::with_continuation_priority. On, a task woken while a worker is polling
+// it (wake_by_ref from inside its own poll, or a wake landing mid-poll) goes onto its queue's
+// continuation list, which workers check before anything freshly spawned.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+thread_local! {
+    // the task this worker is polling right now
+    static POLLING: Cell<Option<TaskId>> = const { Cell::new(None) };
+}
+
+// Held by a worker across one poll of `task`
+pub(crate) struct Polling;
+
+pub(crate) fn polling(task: TaskId) -> Polling {
+    POLLING.set(Some(task));
+    Polling
+}
+
+impl Drop for Polling {
+    fn drop(&mut self) {
+        POLLING.set(None);
+    }
+}
+
+// Whether `task` is being rescheduled by the worker that is polling it, and should go onto
+// the continuation list
+pub(crate) fn is_continuation(task: TaskId) -> bool {
+    ENABLED.load(Ordering::Relaxed) && POLLING.get() == Some(task)
+}
+
+// Same shape as the Lifo stacks, but first in first out: continuations keep their order
+// among themselves and only jump ahead of fresh spawns
+struct List {
+    tasks: Mutex<VecDeque<TaskRunnable>>,
+    len: AtomicUsize,
+}
+
+static HIGH: List = List::new();
+static LOW: List = List::new();
+
+impl List {
+    const fn new() -> Self {
+        Self {
+            tasks: Mutex::new(VecDeque::new()),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+fn list(queue: FutureType) -> &'static List {
+    match queue {
+        FutureType::High => &HIGH,
+        FutureType::Low => &LOW,
+    }
+}
+
+pub(crate) fn push(queue: FutureType, runnable: TaskRunnable) {
+    let list = list(queue);
+    let mut tasks = list.tasks.lock().unwrap();
+    tasks.push_back(runnable);
+    list.len.store(tasks.len(), Ordering::Release);
+}
+
+pub(crate) fn pop(queue: FutureType) -> Option<TaskRunnable> {
+    let list = list(queue);
+    if list.len.load(Ordering::Acquire) == 0 {
+        return None;
+    }
+    let mut tasks = list.tasks.lock().unwrap();
+    let runnable = tasks.pop_front();
+    list.len.store(tasks.len(), Ordering::Release);
+    runnable
+}
+
+pub(crate) fn len(queue: FutureType) -> usize {
+    list(queue).len.load(Ordering::Acquire)
+}
+
+pub(crate) fn drain(queue: FutureType) -> Vec<TaskRunnable> {
+    let list = list(queue);
+    let mut tasks = list.tasks.lock().unwrap();
+    list.len.store(0, Ordering::Release);
+    tasks.drain(..).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{Runtime, join_all, spawn_task, yield_now};
+
+    const YIELDS: usize = 10;
+
+    // Queues a task yielding YIELDS times ahead of a flood of fresh spawns, then runs them all
+    // on one worker and returns the positions in the execution order of the yielding task's
+    // polls
+    fn yielder_polls(continuation_priority: bool) -> Vec<usize> {
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let yielder = spawn_task(
+            async {
+                for _ in 0..YIELDS {
+                    yield_now().await;
+                }
+            },
+            FutureType::High,
+        );
+        let id = yielder.metadata().id();
+        let flood: Vec<_> = (0..50)
+            .map(|_| spawn_task(async {}, FutureType::High))
+            .collect();
+        Runtime::graceful_restart(
+            Runtime::new()
+                .with_high_num(1)
+                .with_low_num(0)
+                .with_order_recording(true)
+                .with_continuation_priority(continuation_priority),
+        );
+        futures_lite::future::block_on(async {
+            yielder.await;
+            join_all(flood).await;
+        });
+        Runtime::take_execution_order()
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, polled)| polled == id)
+            .map(|(position, _)| position)
+            .collect()
+    }
+
+    #[test]
+    fn continuations_run_ahead_of_a_spawn_flood() {
+        // every poll after a yield comes straight after the one before
+        assert_eq!(yielder_polls(true), (0..=YIELDS).collect::<Vec<_>>());
+        // without, every yield sends it to the back, behind the whole flood
+        let polls = yielder_polls(false);
+        assert_eq!(polls.len(), YIELDS + 1);
+        assert_eq!(polls[..2], [0, 51]);
+    }
+}
@@ -0,0 +1,181 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::task::{Context, Poll};
+
+// Set through Runtime::with_coop_budget: how many operations on the runtime's primitives
+// (AsyncMutex locks, condvar waits, receives from a ProgressReceiver, a Generator or a
+// stream_from_iter stream, consume_budget) a task gets per poll.
+// Once it has used them up, those operations return Pending and wake the task right away, so
+// it goes to the back of the queue even if it never awaits anything that is actually
+// pending. Zero, the default, means no budget.
+static BUDGET: AtomicU32 = AtomicU32::new(0);
+
+pub(crate) fn set_budget(budget: Option<u32>) {
+    BUDGET.store(budget.unwrap_or(0), Ordering::Relaxed);
+}
+
+thread_local! {
+    // what is left of the budget of the task being polled on this thread, None outside tasks
+    // or without a budget
+    static REMAINING: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+// Gives the task a fresh budget for one poll, restoring the outer one (a task polled inline
+// from inside another) when dropped
+pub(crate) struct Tick(Option<u32>);
+
+pub(crate) fn tick() -> Tick {
+    let budget = match BUDGET.load(Ordering::Relaxed) {
+        0 => None,
+        budget => Some(budget),
+    };
+    Tick(REMAINING.replace(budget))
+}
+
+impl Drop for Tick {
+    fn drop(&mut self) {
+        REMAINING.set(self.0);
+    }
+}
+
+// Takes one unit of the budget, or yields if there is none left
+pub(crate) fn poll_proceed(cx: &mut Context<'_>) -> Poll<()> {
+    match REMAINING.get() {
+        Some(0) => {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+        Some(remaining) => {
+            REMAINING.set(Some(remaining - 1));
+            Poll::Ready(())
+        }
+        None => Poll::Ready(()),
+    }
+}
+
+// Counts as one operation against the task's budget, yielding if it is spent, for loops
+// driving something the budget doesn't know about, e.g. receiving from a flume channel that
+// always has a message ready
+pub async fn consume_budget() {
+    std::future::poll_fn(poll_proceed).await
+}
+
+// Yield to the other queued tasks once, whatever is left of the budget
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures_lite::StreamExt;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{FutureType, Runtime, join_all, spawn_generator, spawn_task, spawn_with_progress};
+
+    type Trace = Arc<Mutex<Vec<&'static str>>>;
+
+    // Queues a task that does ten operations ahead of a sibling, then runs both on a single
+    // worker, and returns where the sibling's entry ended up among the busy task's. `busy` is
+    // called with the runtime stopped, so whatever it spawns is queued ahead of both.
+    fn sibling_position<Fut>(budget: Option<u32>, busy: impl FnOnce(Trace) -> Fut) -> usize
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let trace = Trace::default();
+        let busy = spawn_task(busy(trace.clone()), FutureType::High);
+        let sibling = {
+            let trace = trace.clone();
+            spawn_task(
+                async move { trace.lock().unwrap().push("sibling") },
+                FutureType::High,
+            )
+        };
+        let mut config = Runtime::new().with_high_num(1).with_low_num(0);
+        if let Some(budget) = budget {
+            config = config.with_coop_budget(budget);
+        }
+        Runtime::graceful_restart(config);
+        futures_lite::future::block_on(join_all(vec![busy, sibling]));
+        let trace = trace.lock().unwrap();
+        assert_eq!(trace.len(), 11);
+        trace.iter().position(|entry| *entry == "sibling").unwrap()
+    }
+
+    async fn consume_ten(trace: Trace) {
+        for _ in 0..10 {
+            consume_budget().await;
+            trace.lock().unwrap().push("busy");
+        }
+    }
+
+    // the updates are all sent by the time the receiving task runs, so no receive is pending
+    fn receive_ten_updates(trace: Trace) -> impl Future<Output = ()> {
+        let (producer, progress) = spawn_with_progress(
+            |sender| async move {
+                for step in 0..10 {
+                    sender.send(step as f64);
+                }
+            },
+            FutureType::High,
+        );
+        async move {
+            let _producer = producer;
+            while progress.recv().await.is_some() {
+                trace.lock().unwrap().push("busy");
+            }
+        }
+    }
+
+    fn receive_ten_values(trace: Trace) -> impl Future<Output = ()> {
+        let mut values = spawn_generator(
+            |yielder| async move {
+                for i in 0..10 {
+                    yielder.yield_value(i);
+                }
+            },
+            FutureType::High,
+        );
+        async move {
+            while values.next().await.is_some() {
+                trace.lock().unwrap().push("busy");
+            }
+        }
+    }
+
+    #[test]
+    fn spent_budget_yields_to_a_sibling() {
+        assert_eq!(sibling_position(None, consume_ten), 10);
+        assert_eq!(sibling_position(Some(3), consume_ten), 3);
+    }
+
+    #[test]
+    fn receiving_from_ready_channels_yields_to_a_sibling() {
+        assert_eq!(sibling_position(None, receive_ten_updates), 10);
+        assert_eq!(sibling_position(Some(3), receive_ten_updates), 3);
+        assert_eq!(sibling_position(None, receive_ten_values), 10);
+        assert_eq!(sibling_position(Some(3), receive_ten_values), 3);
+    }
+}
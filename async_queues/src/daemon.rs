@@ -0,0 +1,78 @@
+use std::sync::Once;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{registry, workers};
+
+// Set through Runtime::with_daemon_workers. Daemon workers (the default) are simply abandoned
+// when the process exits, along with whatever tasks they were running. Otherwise exiting
+// waits for every live task to finish first, from an atexit hook, which is the only place
+// left to run anything once main has returned.
+static DAEMON: AtomicBool = AtomicBool::new(true);
+static HOOK: Once = Once::new();
+
+unsafe extern "C" {
+    fn atexit(hook: extern "C" fn()) -> i32;
+}
+
+pub(crate) fn set_daemon(daemon: bool) {
+    DAEMON.store(daemon, Ordering::Relaxed);
+    if !daemon {
+        // SAFETY: atexit only stores the function pointer, which stays valid for good
+        HOOK.call_once(|| unsafe {
+            atexit(wait_at_exit);
+        });
+    }
+}
+
+extern "C" fn wait_at_exit() {
+    // a task calling process::exit would otherwise wait for itself
+    if DAEMON.load(Ordering::Relaxed) || workers::on_worker() {
+        return;
+    }
+    registry::wait_idle();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::test_support::{run_child, runtime, scenario};
+    use crate::testing::BackgroundFuture;
+    use crate::timer::sleep;
+    use crate::{FutureType, Runtime, spawn_task};
+
+    const FINISHED: &str = "slow task finished";
+
+    #[test]
+    fn exit_waits_for_tasks_only_without_daemon_workers() {
+        if let Some(scenario) = scenario() {
+            let daemon = scenario == "daemon";
+            let _runtime = runtime(Runtime::new().with_daemon_workers(daemon));
+            if daemon {
+                // would keep a non-daemon process alive for good
+                spawn_task(BackgroundFuture::new(), FutureType::Low).detach();
+            }
+            let slow = async {
+                sleep(Duration::from_millis(300)).await;
+                println!("{FINISHED}");
+            };
+            spawn_task(slow, FutureType::High).detach();
+            return;
+        }
+        for (scenario, waits) in [("daemon", false), ("non-daemon", true)] {
+            let started = Instant::now();
+            let output = run_child(
+                "daemon::tests::exit_waits_for_tasks_only_without_daemon_workers",
+                scenario,
+                Duration::from_secs(30),
+            )
+            .unwrap_or_else(|| panic!("the {scenario} process didn't exit"));
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(output.status.success(), "{scenario}: {stdout}");
+            assert_eq!(stdout.contains(FINISHED), waits, "{scenario}: {stdout}");
+            if !waits {
+                assert!(started.elapsed() < Duration::from_secs(10));
+            }
+        }
+    }
+}
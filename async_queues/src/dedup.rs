@@ -0,0 +1,205 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::panic::Location;
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::FutureType;
+
+// Tasks started by spawn_deduplicated that haven't finished yet, one map per key and output
+// type, so the same key used for two different outputs means two different tasks
+type Keyed<K, T> = HashMap<K, SharedTask<T>>;
+static LIVE: LazyLock<Mutex<HashMap<TypeId, Box<dyn Any + Send>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Handle to a task started by spawn_deduplicated, shared by every caller that asked for its
+// key while it ran. Each one resolves to a clone of the task's output. Dropping the handles
+// doesn't cancel the task.
+pub struct SharedTask<T> {
+    inner: Arc<Shared<T>>,
+}
+
+impl<T> Clone for SharedTask<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+struct Shared<T> {
+    outcome: Mutex<Outcome<T>>,
+}
+
+enum Outcome<T> {
+    Running(Vec<Waker>),
+    Done(T),
+    // the future panicked or was dropped before finishing
+    Failed,
+}
+
+impl<T> Shared<T> {
+    // Only the first outcome sticks
+    fn finish(&self, outcome: Outcome<T>) {
+        let mut current = self.outcome.lock().unwrap();
+        if let Outcome::Running(wakers) = &mut *current {
+            let wakers = std::mem::take(wakers);
+            *current = outcome;
+            drop(current);
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+}
+
+impl<T: Clone> Future for SharedTask<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        match &mut *self.inner.outcome.lock().unwrap() {
+            Outcome::Running(wakers) => {
+                if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+            Outcome::Done(output) => Poll::Ready(output.clone()),
+            Outcome::Failed => panic!("deduplicated task panicked"),
+        }
+    }
+}
+
+// Drops the task's entry once it is over, however it ended, and fails the handles if it
+// didn't get to hand out an output
+struct Finished<K: Hash + Eq + Send + 'static, T: Send + 'static> {
+    key: K,
+    shared: Arc<Shared<T>>,
+}
+
+impl<K: Hash + Eq + Send + 'static, T: Send + 'static> Drop for Finished<K, T> {
+    fn drop(&mut self) {
+        let mut live = LIVE.lock().unwrap();
+        if let Some(tasks) = live.get_mut(&TypeId::of::<(K, T)>()) {
+            tasks
+                .downcast_mut::<Keyed<K, T>>()
+                .unwrap()
+                .remove(&self.key);
+        }
+        drop(live);
+        self.shared.finish(Outcome::Failed);
+    }
+}
+
+// Start the future built by `factory` under `key`, unless a task started under the same key is
+// still running, in which case `factory` isn't called and the handle shares that task's
+// output instead, e.g. so concurrent requests for the same resource fetch it once. The key is
+// free again as soon as the task finishes; a panic in the task panics every handle.
+#[track_caller]
+pub fn spawn_deduplicated<K, F, Fut, T>(key: K, factory: F, order: FutureType) -> SharedTask<T>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = T> + Send + 'static,
+    T: Clone + Send + 'static,
+{
+    let mut live = LIVE.lock().unwrap();
+    let tasks = live
+        .entry(TypeId::of::<(K, T)>())
+        .or_insert_with(|| Box::new(Keyed::<K, T>::new()))
+        .downcast_mut::<Keyed<K, T>>()
+        .unwrap();
+    if let Some(task) = tasks.get(&key) {
+        return task.clone();
+    }
+    let task = SharedTask {
+        inner: Arc::new(Shared {
+            outcome: Mutex::new(Outcome::Running(Vec::new())),
+        }),
+    };
+    tasks.insert(key.clone(), task.clone());
+    // spawning can poll the future inline, which would take the lock again when it finishes
+    drop(live);
+    let finished = Finished {
+        key,
+        shared: task.inner.clone(),
+    };
+    let future = factory();
+    let future = async move {
+        let output = future.await;
+        finished.shared.finish(Outcome::Done(output));
+    };
+    crate::spawn(future, order, None, Location::caller()).detach();
+    task
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::Runtime;
+    use crate::test_support::runtime;
+    use crate::timer::sleep;
+
+    const CALLERS: usize = 8;
+
+    // Reads `key`, counting the reads that actually ran
+    fn fetch(runs: &Arc<AtomicUsize>, key: &'static str) -> SharedTask<String> {
+        let runs = runs.clone();
+        spawn_deduplicated(
+            key,
+            move || {
+                runs.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    sleep(Duration::from_millis(100)).await;
+                    format!("contents of {key}")
+                }
+            },
+            FutureType::Low,
+        )
+    }
+
+    #[test]
+    fn concurrent_callers_share_one_run() {
+        let _runtime = runtime(Runtime::new());
+        let runs = Arc::new(AtomicUsize::new(0));
+        // every caller asks for the key at the same moment, each from its own thread
+        let start = Arc::new(Barrier::new(CALLERS));
+        let callers: Vec<_> = (0..CALLERS)
+            .map(|_| {
+                let (runs, start) = (runs.clone(), start.clone());
+                thread::spawn(move || {
+                    start.wait();
+                    futures_lite::future::block_on(fetch(&runs, "a.txt"))
+                })
+            })
+            .collect();
+        let outputs: Vec<_> = callers
+            .into_iter()
+            .map(|caller| caller.join().unwrap())
+            .collect();
+        assert_eq!(outputs, vec!["contents of a.txt"; CALLERS]);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        // another key is another run
+        let other = futures_lite::future::block_on(fetch(&runs, "b.txt"));
+        assert_eq!(other, "contents of b.txt");
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        // the key is free again once its task is done, which may be just after the handles
+        // got the output
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let again = futures_lite::future::block_on(fetch(&runs, "a.txt"));
+            assert_eq!(again, "contents of a.txt");
+            if runs.load(Ordering::SeqCst) == 3 {
+                break;
+            }
+            assert!(Instant::now() < deadline, "the key stayed taken");
+        }
+    }
+}
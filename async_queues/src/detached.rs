@@ -0,0 +1,100 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::task::{Poll, Waker};
+
+use futures_lite::future;
+
+use crate::Task;
+
+// Detached tasks that haven't finished yet. Both waiting flavours are notified each time it
+// drops back to zero.
+static DETACHED: AtomicUsize = AtomicUsize::new(0);
+static IDLE_LOCK: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+static IDLE: Condvar = Condvar::new();
+
+// Detach a task but keep counting it, so Runtime::wait_detached / join_detached can tell when
+// every detached task is done. A bare `task.detach()` isn't tracked.
+pub fn detach<T>(task: Task<T>) {
+    DETACHED.fetch_add(1, Ordering::AcqRel);
+    if !task.metadata().mark_detached() {
+        // it already finished, nothing left to wait for
+        released();
+    }
+    task.detach();
+}
+
+// Called once a detached task's future is gone
+pub(crate) fn released() {
+    if DETACHED.fetch_sub(1, Ordering::AcqRel) == 1 {
+        let mut wakers = IDLE_LOCK.lock().unwrap();
+        wakers.drain(..).for_each(Waker::wake);
+        IDLE.notify_all();
+    }
+}
+
+pub(crate) fn wait() {
+    let mut guard = IDLE_LOCK.lock().unwrap();
+    while DETACHED.load(Ordering::Acquire) != 0 {
+        guard = IDLE.wait(guard).unwrap();
+    }
+}
+
+pub(crate) fn join() -> impl Future<Output = ()> {
+    future::poll_fn(|cx| {
+        let mut wakers = IDLE_LOCK.lock().unwrap();
+        if DETACHED.load(Ordering::Acquire) == 0 {
+            return Poll::Ready(());
+        }
+        wakers.push(cx.waker().clone());
+        Poll::Pending
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::testing::PollCountFuture;
+    use crate::{FutureType, Runtime, spawn_task};
+
+    #[test]
+    fn wait_detached_returns_once_every_counter_reached_three() {
+        let _runtime = runtime(Runtime::new());
+        let counters: Vec<_> = (0..4)
+            .map(|i| {
+                let counter = PollCountFuture::new(3);
+                let polls = counter.polls();
+                let order = if i % 2 == 0 {
+                    FutureType::High
+                } else {
+                    FutureType::Low
+                };
+                detach(spawn_task(counter, order));
+                polls
+            })
+            .collect();
+        Runtime::wait_detached();
+        for polls in &counters {
+            assert_eq!(polls.load(Ordering::Acquire), 3);
+        }
+    }
+
+    #[test]
+    fn join_detached_waits_from_inside_a_task() {
+        let _runtime = runtime(Runtime::new());
+        let counter = PollCountFuture::new(3);
+        let polls = counter.polls();
+        let waiter = spawn_task(
+            async move {
+                detach(spawn_task(counter, FutureType::Low));
+                Runtime::join_detached().await;
+                polls.load(Ordering::Acquire)
+            },
+            FutureType::High,
+        );
+        assert_eq!(futures_lite::future::block_on(waiter), 3);
+    }
+}
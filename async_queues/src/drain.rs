@@ -0,0 +1,94 @@
+#[cfg(feature = "task-registry")]
+use crate::TaskInfo;
+use crate::{
+    FutureType, HIGH_CHANNEL, LOW_CHANNEL, TaskId, TaskRunnable, continuation, enqueue, lifo,
+};
+
+// A task taken off a queue by Runtime::drain_queued before any worker got to it. It sits
+// here, unpolled, until it is resumed; dropping it cancels the task.
+pub struct PendingTask {
+    runnable: TaskRunnable,
+    queue: FutureType,
+}
+
+impl PendingTask {
+    pub fn id(&self) -> TaskId {
+        self.runnable.metadata().id()
+    }
+
+    #[cfg(feature = "task-registry")]
+    pub fn info(&self) -> TaskInfo {
+        self.runnable.metadata().info()
+    }
+
+    // The queue it was drained from
+    pub fn queue(&self) -> FutureType {
+        self.queue
+    }
+
+    // Hand it back to the scheduler it was spawned with, normally the queue it came from
+    pub fn resume(self) {
+        self.runnable.schedule();
+    }
+
+    // Put it on `queue` instead, e.g. to replay drained low priority work as high
+    pub fn resume_on(self, queue: FutureType) {
+        enqueue(self.runnable, queue);
+    }
+}
+
+// Empties both queues, high first, in the order the workers would have taken the tasks. A
+// worker taking a task at the same moment keeps it; tasks being polled or waiting to be woken
+// aren't in a queue.
+pub(crate) fn drain_queued() -> Vec<PendingTask> {
+    [FutureType::High, FutureType::Low]
+        .into_iter()
+        .flat_map(|queue| {
+            let channel = match queue {
+                FutureType::High => &HIGH_CHANNEL.1,
+                FutureType::Low => &LOW_CHANNEL.1,
+            };
+            continuation::drain(queue)
+                .into_iter()
+                .chain(lifo::drain(queue))
+                .chain(channel.drain())
+                .map(move |runnable| PendingTask { runnable, queue })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{Runtime, join_all, spawn_task};
+
+    #[test]
+    fn drained_tasks_run_once_resumed_on_a_working_runtime() {
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let tasks: Vec<_> = (0..6)
+            .map(|i| {
+                let queue = if i % 2 == 0 {
+                    FutureType::High
+                } else {
+                    FutureType::Low
+                };
+                spawn_task(async move { i * i }, queue)
+            })
+            .collect();
+        let pending = Runtime::drain_queued();
+        assert_eq!(pending.len(), 6);
+        // high first, each queue in spawn order
+        let queues: Vec<_> = pending.iter().map(PendingTask::queue).collect();
+        assert_eq!(queues[..3], [FutureType::High; 3]);
+        assert_eq!(queues[3..], [FutureType::Low; 3]);
+        assert!(Runtime::drain_queued().is_empty());
+
+        Runtime::graceful_restart(Runtime::new());
+        for task in pending {
+            task.resume();
+        }
+        let outputs = futures_lite::future::block_on(join_all(tasks));
+        assert_eq!(outputs, [0, 1, 4, 9, 16, 25]);
+    }
+}
@@ -0,0 +1,23 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::TaskId;
+
+// Set through Runtime::with_order_recording: every time a worker starts polling a task, its id
+// is appended here, so a test can check the exact order tasks ran in
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static ORDER: Mutex<Vec<TaskId>> = Mutex::new(Vec::new());
+
+pub(crate) fn set_recording(recording: bool) {
+    RECORDING.store(recording, Ordering::Relaxed);
+}
+
+pub(crate) fn record(task: TaskId) {
+    if RECORDING.load(Ordering::Relaxed) {
+        ORDER.lock().unwrap().push(task);
+    }
+}
+
+pub(crate) fn take() -> Vec<TaskId> {
+    std::mem::take(&mut *ORDER.lock().unwrap())
+}
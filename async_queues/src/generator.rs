@@ -0,0 +1,90 @@
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use flume::Sender;
+use flume::r#async::RecvStream;
+use futures_lite::Stream;
+
+use crate::{FutureType, Task, coop};
+
+// Handed to the generator body to emit values with
+#[derive(Clone)]
+pub struct Yielder<T> {
+    sender: Sender<T>,
+}
+
+impl<T> Yielder<T> {
+    // never blocks; values are buffered until the consumer gets to them, and dropped once
+    // nobody is listening anymore
+    pub fn yield_value(&self, value: T) {
+        let _ = self.sender.send(value);
+    }
+}
+
+// The values a generator task yields, in order. Ends once the task has finished and every
+// value has been read; dropping it cancels the task.
+pub struct Generator<T: 'static> {
+    values: RecvStream<'static, T>,
+    _task: Task<()>,
+}
+
+impl<T> Stream for Generator<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if coop::poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
+        Pin::new(&mut self.values).poll_next(cx)
+    }
+}
+
+// Spawn a task that produces a sequence of values over time, consumed as a Stream, e.g.
+// `spawn_generator(|y| async move { for i in 0..3 { y.yield_value(i) } }, FutureType::Low)`
+#[track_caller]
+pub fn spawn_generator<F, Fut, T>(factory: F, order: FutureType) -> Generator<T>
+where
+    F: FnOnce(Yielder<T>) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = flume::unbounded();
+    let future = factory(Yielder { sender });
+    let task = crate::spawn(future, order, None, Location::caller());
+    Generator {
+        values: receiver.into_stream(),
+        _task: task,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_lite::StreamExt;
+
+    use super::*;
+    use crate::Runtime;
+    use crate::test_support::runtime;
+    use crate::timer::sleep;
+
+    #[test]
+    fn values_arrive_in_the_order_they_were_yielded() {
+        let _runtime = runtime(Runtime::new());
+        let squares = spawn_generator(
+            |yielder| async move {
+                for i in 0..10u32 {
+                    yielder.yield_value(i * i);
+                    if i % 3 == 0 {
+                        sleep(Duration::from_millis(2)).await;
+                    }
+                }
+            },
+            FutureType::Low,
+        );
+        let values = futures_lite::future::block_on(squares.collect::<Vec<_>>());
+        assert_eq!(values, (0..10).map(|i| i * i).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,42 @@
+use std::panic::Location;
+
+use futures_task::{FutureObj, Spawn, SpawnError};
+
+use crate::FutureType;
+
+// Lets code written against the futures crate's Spawn trait (or SpawnExt) spawn onto this
+// runtime. Every future spawned through it is a detached low priority task, as there is no
+// way to ask for a priority or get a Task back through the trait.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExecutorHandle;
+
+impl Spawn for ExecutorHandle {
+    #[track_caller]
+    fn spawn_obj(&self, future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+        crate::spawn(future, FutureType::Low, None, Location::caller()).detach();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Runtime;
+    use crate::test_support::runtime;
+
+    // What a library taking any executor would do with it
+    fn start_worker(spawner: &impl Spawn, done: flume::Sender<u32>) {
+        let job = async move { done.send(6 * 7).unwrap() };
+        spawner.spawn_obj(FutureObj::new(Box::new(job))).unwrap();
+    }
+
+    #[test]
+    fn generic_spawner_runs_tasks_on_the_runtime() {
+        let _runtime = runtime(Runtime::new());
+        let (done, finished) = flume::unbounded();
+        start_worker(&Runtime::handle(), done);
+        assert_eq!(finished.recv(), Ok(42));
+        #[cfg(feature = "metrics")]
+        assert_eq!(Runtime::take_metrics().spawned(), 1);
+    }
+}
@@ -0,0 +1,224 @@
+#[cfg(feature = "task-registry")]
+use std::fmt::Write;
+use std::time::Duration;
+#[cfg(feature = "task-registry")]
+use std::time::Instant;
+
+#[cfg(feature = "metrics")]
+use crate::metrics;
+#[cfg(feature = "task-registry")]
+use crate::registry;
+#[cfg(any(feature = "metrics", feature = "task-registry"))]
+use crate::watchdog;
+use crate::{FutureType, capacity, panics};
+
+// A poll running longer than this counts as stuck when Runtime::with_slow_poll_watchdog hasn't
+// set a threshold of its own
+#[cfg(any(feature = "metrics", feature = "task-registry"))]
+const DEFAULT_STUCK_AFTER: Duration = Duration::from_secs(1);
+
+// Go/no-go summary from Runtime::health, e.g. for a readiness probe
+#[derive(Clone, Debug, PartialEq)]
+pub enum Health {
+    Ok,
+    Degraded(Vec<HealthIssue>),
+}
+
+impl Health {
+    pub fn is_ok(&self) -> bool {
+        *self == Health::Ok
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HealthIssue {
+    // a worker has been in the same poll for longer than the watchdog threshold. Polls are only
+    // timed with the metrics feature, so this is never reported without it.
+    Stuck {
+        worker: usize,
+        polling_for: Duration,
+    },
+    // a queue holds at least the capacity set with Runtime::with_queue_capacity
+    QueueFull {
+        queue: FutureType,
+        queued: usize,
+    },
+    // tasks panicked since the previous check
+    Panics {
+        count: u64,
+    },
+}
+
+pub(crate) fn check() -> Health {
+    let mut issues = Vec::new();
+    #[cfg(feature = "metrics")]
+    {
+        let stuck_after = watchdog::threshold().unwrap_or(DEFAULT_STUCK_AFTER);
+        let stuck = metrics::current_polls()
+            .into_iter()
+            .filter(|(_, polling_for)| *polling_for >= stuck_after)
+            .map(|(worker, polling_for)| HealthIssue::Stuck {
+                worker,
+                polling_for,
+            });
+        issues.extend(stuck);
+    }
+    for queue in [FutureType::High, FutureType::Low] {
+        if let Some(queued) = capacity::over(queue) {
+            issues.push(HealthIssue::QueueFull { queue, queued });
+        }
+    }
+    let count = panics::take_panics();
+    if count > 0 {
+        issues.push(HealthIssue::Panics { count });
+    }
+    if issues.is_empty() {
+        Health::Ok
+    } else {
+        Health::Degraded(issues)
+    }
+}
+
+// One line per live task whose last poll ended more than the watchdog threshold (1s if that's
+// off) ago, or that has been around that long without a poll ending: stuck in a poll, waiting
+// for a wake-up that may never come, or starved in a queue
+#[cfg(feature = "task-registry")]
+pub(crate) fn dump_stuck_tasks() -> String {
+    let stuck_after = watchdog::threshold().unwrap_or(DEFAULT_STUCK_AFTER);
+    let now = Instant::now();
+    let mut dump = String::new();
+    for task in registry::live_tasks() {
+        let since = task.last_polled.unwrap_or(task.spawned_at);
+        if now.duration_since(since) < stuck_after {
+            continue;
+        }
+        let _ = write!(dump, "{} ", task.id);
+        if let Some(name) = &task.name {
+            let _ = write!(dump, "{name:?} ");
+        }
+        let _ = write!(
+            dump,
+            "{:?} {:?}, spawned at {}",
+            task.state, task.priority, task.location
+        );
+        if !task.labels.is_empty() {
+            dump.push_str(", labels");
+        }
+        for (key, value) in &task.labels {
+            let _ = write!(dump, " {key}={value}");
+        }
+        let _ = match task.last_polled {
+            Some(last_polled) => writeln!(
+                dump,
+                ", last polled {:?} ago",
+                now.duration_since(last_polled)
+            ),
+            None => writeln!(
+                dump,
+                ", not polled to the end in {:?}",
+                now.duration_since(task.spawned_at)
+            ),
+        };
+    }
+    dump
+}
+
+// Both tests need a feature: stuck workers are found through the metrics, stuck tasks through
+// the registry
+#[cfg(all(test, any(feature = "metrics", feature = "task-registry")))]
+mod tests {
+    #[cfg(feature = "metrics")]
+    use std::sync::Arc;
+    #[cfg(feature = "metrics")]
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    #[cfg(feature = "metrics")]
+    use std::time::Instant;
+
+    use super::*;
+    use crate::Runtime;
+    #[cfg(feature = "task-registry")]
+    use crate::spawn_named_task;
+    #[cfg(feature = "metrics")]
+    use crate::spawn_task;
+    use crate::test_support::runtime;
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn stuck_task_degrades_health_until_it_finishes() {
+        let threshold = Duration::from_millis(50);
+        let _runtime = runtime(Runtime::new().with_slow_poll_watchdog(threshold));
+        // panics left over from earlier tests aren't this runtime's
+        panics::take_panics();
+        assert_eq!(Runtime::health(), Health::Ok);
+
+        let release = Arc::new(AtomicBool::new(false));
+        let stuck = spawn_task(
+            {
+                let release = release.clone();
+                async move {
+                    while !release.load(Ordering::Relaxed) {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                }
+            },
+            FutureType::High,
+        );
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let issues = loop {
+            if let Health::Degraded(issues) = Runtime::health() {
+                break issues;
+            }
+            assert!(Instant::now() < deadline, "the stuck task went unnoticed");
+            thread::sleep(Duration::from_millis(10));
+        };
+        let [HealthIssue::Stuck { polling_for, .. }] = issues[..] else {
+            panic!("expected one stuck worker, got {issues:?}");
+        };
+        assert!(polling_for >= threshold);
+
+        release.store(true, Ordering::Relaxed);
+        futures_lite::future::block_on(stuck);
+        // the worker may still be wrapping up the poll that woke us
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !Runtime::health().is_ok() {
+            assert!(
+                Instant::now() < deadline,
+                "still degraded once the task finished"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[cfg(feature = "task-registry")]
+    #[test]
+    fn stuck_task_dump_names_the_spawn_site() {
+        let threshold = Duration::from_millis(50);
+        let _runtime = runtime(
+            Runtime::new()
+                .with_high_num(0)
+                .with_low_num(0)
+                .with_slow_poll_watchdog(threshold),
+        );
+        let line = line!() + 1;
+        let stuck = spawn_named_task("stuck", async {}, FutureType::Low);
+        thread::sleep(threshold * 2);
+        // too young to count as stuck
+        let fresh = spawn_named_task("fresh", async {}, FutureType::Low);
+
+        let dump = Runtime::dump_stuck_tasks();
+        let spawned_at = format!("spawned at {}:{line}:", file!());
+        let [entry] = dump.lines().collect::<Vec<_>>()[..] else {
+            panic!("expected one stuck task, got {dump:?}");
+        };
+        assert!(entry.contains("\"stuck\""), "{entry}");
+        assert!(entry.contains(&spawned_at), "{entry}");
+        assert!(entry.contains("not polled to the end"), "{entry}");
+
+        Runtime::graceful_restart(Runtime::new().with_high_num(1).with_low_num(1));
+        futures_lite::future::block_on(async {
+            stuck.await;
+            fresh.await;
+        });
+    }
+}
@@ -0,0 +1,203 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::bias;
+
+// Set through Runtime::with_park_when_idle. Either way an idle worker sleeps on IDLE and is
+// woken as soon as something is enqueued, be it a spawn or a wake-up from the timer thread.
+// Off, it also wakes every 100ms to look at the queues on its own; on, it sleeps until woken,
+// so an idle runtime costs no CPU at all.
+static PARK_WHEN_IDLE: AtomicBool = AtomicBool::new(false);
+
+// Bumped for every enqueue. A worker notes it before looking at the queues and only sleeps if
+// it hasn't moved since, so a task queued in between isn't missed.
+static EPOCH: AtomicU64 = AtomicU64::new(0);
+// Workers sleeping on IDLE, so an enqueue only takes the lock when there is someone to wake.
+// WAKING is set while a worker has been woken and hasn't left IDLE yet: it is about to look at
+// the queues anyway, so enqueues meanwhile don't wake another one. Instead, once it has taken
+// a task, it calls notify itself if more are queued, and the next one it wakes does the same.
+// Only changed under LOCK.
+static SLEEPING: AtomicUsize = AtomicUsize::new(0);
+static WAKING: AtomicBool = AtomicBool::new(false);
+static LOCK: Mutex<()> = Mutex::new(());
+static IDLE: Condvar = Condvar::new();
+
+const NAP: Duration = Duration::from_millis(100);
+// Yields a worker gives up its CPU for before it goes to sleep. Under a steady stream of tasks
+// the next one usually turns up within a few, and not sleeping saves both the worker and the
+// enqueue that would have had to wake it a trip through LOCK.
+const SPINS: usize = 64;
+
+pub(crate) fn set_park_when_idle(park: bool) {
+    PARK_WHEN_IDLE.store(park, Ordering::Relaxed);
+    // workers already asleep re-check which mode they are in
+    wake_all();
+}
+
+pub(crate) fn epoch() -> u64 {
+    EPOCH.load(Ordering::SeqCst)
+}
+
+// Called after every enqueue
+pub(crate) fn notify() {
+    // SeqCst pairs with wait: either the worker sees the new epoch or we see it sleeping
+    EPOCH.fetch_add(1, Ordering::SeqCst);
+    if SLEEPING.load(Ordering::SeqCst) == 0 || WAKING.load(Ordering::SeqCst) {
+        return;
+    }
+    let _lock = LOCK.lock().unwrap();
+    if SLEEPING.load(Ordering::SeqCst) == 0 || WAKING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    // without stealing, any one worker may be from the pool that can't take the task
+    if bias::stealing() {
+        IDLE.notify_one();
+    } else {
+        IDLE.notify_all();
+    }
+}
+
+// Wakes every sleeping worker, e.g. so retired ones notice a graceful restart
+pub(crate) fn wake_all() {
+    EPOCH.fetch_add(1, Ordering::SeqCst);
+    let _lock = LOCK.lock().unwrap();
+    IDLE.notify_all();
+}
+
+// Called by a worker that found both queues empty after reading `epoch`
+pub(crate) fn wait(epoch: u64) {
+    for _ in 0..SPINS {
+        thread::yield_now();
+        if EPOCH.load(Ordering::SeqCst) != epoch {
+            return;
+        }
+    }
+    let mut lock = LOCK.lock().unwrap();
+    SLEEPING.fetch_add(1, Ordering::SeqCst);
+    while EPOCH.load(Ordering::SeqCst) == epoch {
+        if PARK_WHEN_IDLE.load(Ordering::Relaxed) {
+            lock = IDLE.wait(lock).unwrap();
+        } else {
+            let (relocked, timeout) = IDLE.wait_timeout(lock, NAP).unwrap();
+            lock = relocked;
+            if timeout.timed_out() {
+                break;
+            }
+        }
+    }
+    SLEEPING.fetch_sub(1, Ordering::SeqCst);
+    WAKING.store(false, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{FutureType, Runtime, join_all, spawn_task};
+
+    #[test]
+    fn a_burst_wakes_every_parked_worker() {
+        let _runtime = runtime(
+            Runtime::new()
+                .with_high_num(4)
+                .with_low_num(0)
+                .with_park_when_idle(true),
+        );
+        // all four asleep by now
+        thread::sleep(Duration::from_millis(50));
+        let task_time = Duration::from_millis(300);
+        let start = Instant::now();
+        let tasks: Vec<_> = (0..4)
+            .map(|_| spawn_task(async move { thread::sleep(task_time) }, FutureType::High))
+            .collect();
+        futures_lite::future::block_on(join_all(tasks));
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed < task_time * 2,
+            "4 tasks on 4 workers took {elapsed:?}"
+        );
+    }
+
+    #[test]
+    fn parked_workers_pick_up_a_burst_right_away() {
+        let _runtime = runtime(
+            Runtime::new()
+                .with_high_num(4)
+                .with_low_num(4)
+                .with_park_when_idle(true),
+        );
+        for _ in 0..5 {
+            // every worker parked by now
+            thread::sleep(Duration::from_millis(50));
+            let start = Instant::now();
+            let (done, finished) = flume::unbounded();
+            for i in 0..8 {
+                let done = done.clone();
+                let order = if i % 2 == 0 {
+                    FutureType::High
+                } else {
+                    FutureType::Low
+                };
+                spawn_task(async move { done.send(()).unwrap() }, order).detach();
+            }
+            // a worker that only noticed the burst once it woke up on its own would take up to
+            // a NAP, and a parked one never wakes up on its own
+            for _ in 0..8 {
+                finished
+                    .recv_deadline(start + NAP / 4)
+                    .expect("the burst wasn't picked up right away");
+            }
+        }
+    }
+
+    // CPU time used by every thread of the process so far, in nanoseconds, read from procfs.
+    // Threads that have exited drop out of the sum.
+    #[cfg(target_os = "linux")]
+    fn cpu_nanos() -> u64 {
+        std::fs::read_dir("/proc/self/task")
+            .unwrap()
+            .filter_map(|task| std::fs::read_to_string(task.ok()?.path().join("schedstat")).ok())
+            // time on the CPU is the first field
+            .map(|stat| stat.split(' ').next().unwrap().parse::<u64>().unwrap())
+            .sum()
+    }
+
+    // CPU the process uses over 10 naps with 8 idle workers, parked or polling
+    #[cfg(target_os = "linux")]
+    fn idle_cpu(park: bool) -> Duration {
+        let _runtime = runtime(
+            Runtime::new()
+                .with_high_num(4)
+                .with_low_num(4)
+                .with_park_when_idle(park),
+        );
+        // the workers retired by the restart are gone, and the new ones idle
+        while Runtime::worker_count(FutureType::High) + Runtime::worker_count(FutureType::Low) > 8 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(NAP * 2);
+        let before = cpu_nanos();
+        thread::sleep(NAP * 10);
+        let used = Duration::from_nanos(cpu_nanos().saturating_sub(before));
+        // parked workers still wake up for new work
+        let task = spawn_task(async { 5 }, FutureType::Low);
+        assert_eq!(futures_lite::future::block_on(task), 5);
+        used
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parked_runtime_uses_a_fraction_of_the_cpu_of_a_polling_one() {
+        let polling = idle_cpu(false);
+        let parked = idle_cpu(true);
+        assert!(
+            parked * 4 < polling,
+            "{parked:?} of CPU while parked, {polling:?} while polling"
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{TaskId, TaskRunnable, panics};
+
+// How many polls a fresh task gets on the spawning thread before it is handed to a worker,
+// set through Runtime::with_inline_polls. 0 (the default) always enqueues.
+static INLINE_POLLS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // the task currently being polled inline on this thread, if any
+    static INLINING: Cell<Option<TaskId>> = const { Cell::new(None) };
+    // where that task lands when it wakes itself during an inline poll
+    static REQUEUED: RefCell<Option<TaskRunnable>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn set_inline_polls(polls: usize) {
+    INLINE_POLLS.store(polls, Ordering::Relaxed);
+}
+
+// Called from the schedule functions: keeps the runnable on this thread if it belongs to the
+// task being inlined here, otherwise hands it back to be queued as usual
+pub(crate) fn capture(runnable: TaskRunnable) -> Option<TaskRunnable> {
+    if INLINING.get() != Some(runnable.metadata().id()) {
+        return Some(runnable);
+    }
+    REQUEUED.set(Some(runnable));
+    None
+}
+
+struct Inlining;
+
+impl Drop for Inlining {
+    fn drop(&mut self) {
+        INLINING.set(None);
+    }
+}
+
+// Polls a freshly spawned task up to the configured number of times right here. Returns the
+// runnable if it still needs a worker: inlining is off, the task kept waking itself past the
+// limit, or we are already inside an inline poll (a task spawning from its first polls gets
+// queued normally instead of recursing). A task that goes Pending without waking itself is
+// left to whoever wakes it; it gets queued from there.
+pub(crate) fn run_inline(runnable: TaskRunnable) -> Option<TaskRunnable> {
+    let polls = INLINE_POLLS.load(Ordering::Relaxed);
+    if polls == 0 || INLINING.get().is_some() {
+        return Some(runnable);
+    }
+
+    INLINING.set(Some(runnable.metadata().id()));
+    let _inlining = Inlining;
+    let mut runnable = runnable;
+    for _ in 0..polls {
+        // a panic is handled by the panic policy, same as on a worker
+        panics::run(|| {
+            runnable.run();
+        });
+        match REQUEUED.take() {
+            Some(next) => runnable = next,
+            None => return None,
+        }
+    }
+    Some(runnable)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::runtime;
+    use crate::testing::PollCountFuture;
+    use crate::{FutureType, Runtime, spawn_task};
+
+    #[test]
+    fn task_done_within_the_inline_polls_is_never_queued() {
+        let _runtime = runtime(
+            Runtime::new()
+                .with_inline_polls(3)
+                .with_order_recording(true),
+        );
+        let task = spawn_task(PollCountFuture::new(3), FutureType::High);
+        assert!(task.is_finished());
+        assert_eq!(futures_lite::future::block_on(task), 3);
+        assert!(Runtime::take_execution_order().is_empty());
+        #[cfg(feature = "metrics")]
+        assert_eq!(Runtime::metrics().max_queued(FutureType::High), 0);
+
+        // one poll more than that and it goes to a worker for its last poll
+        let task = spawn_task(PollCountFuture::new(4), FutureType::High);
+        let id = task.metadata().id();
+        assert_eq!(futures_lite::future::block_on(task), 4);
+        assert_eq!(Runtime::take_execution_order(), [id]);
+    }
+}
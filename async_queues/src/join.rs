@@ -0,0 +1,509 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, Location};
+use std::pin::{Pin, pin};
+use std::sync::{Arc, LazyLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_lite::{FutureExt, future};
+
+use crate::registry::Expired;
+use crate::timer::{Elapsed, timeout};
+use crate::{FutureType, HIGHQUEUE, Task, workers};
+
+// Polls every future on each wake-up and resolves once all of them are done,
+// with the outputs in the same order as the input.
+pub struct JoinAll<F: Future> {
+    futures: Vec<Option<Pin<Box<F>>>>,
+    outputs: Vec<Option<F::Output>>,
+}
+
+pub fn join_all<I>(futures: I) -> JoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    let futures: Vec<_> = futures.into_iter().map(|f| Some(Box::pin(f))).collect();
+    let outputs = futures.iter().map(|_| None).collect();
+    JoinAll { futures, outputs }
+}
+
+impl<F: Future> Unpin for JoinAll<F> {}
+
+impl<F: Future> Future for JoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut pending = false;
+        for (slot, output) in this.futures.iter_mut().zip(this.outputs.iter_mut()) {
+            if let Some(future) = slot {
+                match future.as_mut().poll(cx) {
+                    Poll::Ready(value) => {
+                        *output = Some(value);
+                        *slot = None;
+                    }
+                    Poll::Pending => pending = true,
+                }
+            }
+        }
+        if pending {
+            return Poll::Pending;
+        }
+        Poll::Ready(this.outputs.iter_mut().map(|o| o.take().unwrap()).collect())
+    }
+}
+
+// Hand a clone of `input` to every handler and wait for all of them, e.g. to notify several
+// subscribers of the same event. The handlers' futures are polled together by whoever awaits
+// this, with the outputs in handler order; spawn inside a handler to run it on a worker.
+pub fn fan_out<I, H, Fut>(input: I, handlers: Vec<H>) -> JoinAll<Fut>
+where
+    I: Clone,
+    H: Fn(I) -> Fut,
+    Fut: Future,
+{
+    join_all(handlers.iter().map(|handler| handler(input.clone())))
+}
+
+// Bulk counterpart to `timeout`: wait for every future (typically a batch of tasks) but give
+// up once `duration` has passed overall. On timeout the futures are dropped, which for tasks
+// means the stragglers are cancelled.
+pub async fn join_all_timeout<I>(
+    futures: I,
+    duration: Duration,
+) -> Result<Vec<<I::Item as Future>::Output>, Elapsed>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    timeout(duration, join_all(futures)).await
+}
+
+// Drive `a` and `b` together and return the output of whichever finishes last, dropping the
+// other one's, e.g. to wait until two updates have both settled when only the final state
+// matters. If both finish in the same poll, `b` counts as last. To keep both outputs, zip
+// them instead: `future::zip(a, b).await` gives the tuple.
+pub async fn join_last<A, B, T>(a: A, b: B) -> T
+where
+    A: Future<Output = T>,
+    B: Future<Output = T>,
+{
+    let mut a = pin!(Some(a));
+    let mut b = pin!(Some(b));
+    future::poll_fn(|cx| {
+        if let Some(future) = a.as_mut().as_pin_mut()
+            && let Poll::Ready(output) = future.poll(cx)
+        {
+            if b.is_none() {
+                return Poll::Ready(output);
+            }
+            a.set(None);
+        }
+        if let Some(future) = b.as_mut().as_pin_mut()
+            && let Poll::Ready(output) = future.poll(cx)
+        {
+            if a.is_none() {
+                return Poll::Ready(output);
+            }
+            b.set(None);
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+// Run `f` over every item as its own task, with at most `limit` of them spawned at a time, and
+// collect the outputs in input order. The next item is only spawned once a running one has
+// finished, so the rest wait here rather than in the queue.
+#[track_caller]
+pub fn map_concurrent<I, O, F, Fut>(
+    items: Vec<I>,
+    limit: usize,
+    mut f: F,
+    order: FutureType,
+) -> impl Future<Output = Vec<O>>
+where
+    F: FnMut(I) -> Fut,
+    Fut: Future<Output = O> + Send + 'static,
+    O: Send + 'static,
+{
+    let location = Location::caller();
+    let limit = limit.max(1);
+    let mut outputs: Vec<Option<O>> = items.iter().map(|_| None).collect();
+    let mut items = items.into_iter().enumerate();
+    let mut running: Vec<(usize, Task<O>)> = Vec::with_capacity(limit);
+    async move {
+        future::poll_fn(|cx| {
+            loop {
+                while running.len() < limit
+                    && let Some((index, item)) = items.next()
+                {
+                    running.push((index, crate::spawn(f(item), order, None, location)));
+                }
+                if running.is_empty() {
+                    return Poll::Ready(());
+                }
+                let before = running.len();
+                running.retain_mut(|(index, task)| match Pin::new(task).poll(cx) {
+                    Poll::Ready(output) => {
+                        outputs[*index] = Some(output);
+                        false
+                    }
+                    Poll::Pending => true,
+                });
+                // nothing finished, so there's no free slot to fill either
+                if running.len() == before {
+                    return Poll::Pending;
+                }
+            }
+        })
+        .await;
+        outputs.into_iter().map(Option::unwrap).collect()
+    }
+}
+
+// Data-parallel map for CPU-bound work: split `items` into one chunk per high worker, run each
+// chunk as a high task and block until all of them are done, with the outputs in input order.
+// It blocks the calling thread like join!, so call it from outside the runtime; from inside a
+// task it would hold up a worker that one of the chunks may be waiting for.
+#[track_caller]
+pub fn parallel_map<I, O, F>(items: Vec<I>, f: F) -> Vec<O>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+    F: Fn(I) -> O + Send + Sync + 'static,
+{
+    let location = Location::caller();
+    // make sure the pool exists so the worker count is final
+    LazyLock::force(&HIGHQUEUE);
+    let workers = workers::count(FutureType::High).max(1);
+    let chunk_len = items.len().div_ceil(workers).max(1);
+    let f = Arc::new(f);
+    let mut items = items.into_iter().peekable();
+    let mut chunks = Vec::with_capacity(workers);
+    while items.peek().is_some() {
+        let chunk: Vec<I> = items.by_ref().take(chunk_len).collect();
+        let f = f.clone();
+        let task = async move { chunk.into_iter().map(|item| f(item)).collect::<Vec<O>>() };
+        chunks.push(crate::spawn(task, FutureType::High, None, location));
+    }
+    future::block_on(join_all(chunks))
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+// A group of tasks whose results are collected in completion order rather than spawn order.
+// Dropping the set cancels whatever is still running.
+pub struct JoinSet<T> {
+    tasks: Vec<Task<T>>,
+}
+
+impl<T: Send + 'static> JoinSet<T> {
+    pub fn new() -> Self {
+        Self { tasks: Vec::new() }
+    }
+
+    #[track_caller]
+    pub fn spawn<F>(&mut self, future: F, order: FutureType)
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let task = crate::spawn(future, order, None, Location::caller());
+        self.tasks.push(task);
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    // Output of whichever task finishes next, or None once the set is empty
+    pub async fn join_next(&mut self) -> Option<T> {
+        future::poll_fn(|cx| self.poll_join_next(cx)).await
+    }
+
+    // join_next with a deadline, for event loops that check on the group between other work.
+    // Err(Elapsed) only means nothing finished within `duration`; the tasks keep running and
+    // a later call picks up their results.
+    pub async fn join_next_timeout(&mut self, duration: Duration) -> Result<Option<T>, Elapsed> {
+        timeout(duration, self.join_next()).await
+    }
+
+    fn poll_join_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if self.tasks.is_empty() {
+            return Poll::Ready(None);
+        }
+        for index in 0..self.tasks.len() {
+            if let Poll::Ready(output) = Pin::new(&mut self.tasks[index]).poll(cx) {
+                drop(self.tasks.swap_remove(index));
+                return Poll::Ready(Some(output));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<T: Send + 'static> Default for JoinSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Why a task didn't produce its output
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JoinError {
+    // the task panicked, with the panic message
+    Panicked(String),
+    // the task was cancelled before it finished, e.g. by Runtime::cancel_spawned_before or a
+    // dropped PendingTask. A panic under PanicPolicy::Propagate ends up here too, as the
+    // panic went to the worker rather than the task.
+    Cancelled,
+    // the task was aborted for still running past its TTL, see Runtime::with_task_ttl
+    Ttl,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Panicked(message) => write!(f, "task panicked: {message}"),
+            Self::Cancelled => f.write_str("task was cancelled"),
+            Self::Ttl => f.write_str("task outlived its TTL"),
+        }
+    }
+}
+
+impl Error for JoinError {}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Box<dyn Any>".to_string(),
+        },
+    }
+}
+
+// `Task` is async_task's type, so the panic-free way to await one comes as an extension trait
+pub trait TaskExt<T> {
+    // Await the task, getting a panic or cancellation back as Err instead of having it unwind
+    // into the awaiting code the way awaiting the Task itself does
+    fn join(self) -> impl Future<Output = Result<T, JoinError>> + Send;
+}
+
+impl<T: Send + 'static> TaskExt<T> for Task<T> {
+    async fn join(self) -> Result<T, JoinError> {
+        let deadline = self.metadata().deadline();
+        match AssertUnwindSafe(self.fallible()).catch_unwind().await {
+            Ok(Some(output)) => Ok(output),
+            Ok(None) if deadline.as_ref().is_some_and(|deadline| deadline.passed()) => {
+                Err(JoinError::Ttl)
+            }
+            Ok(None) => Err(JoinError::Cancelled),
+            Err(payload) if payload.is::<Expired>() => Err(JoinError::Ttl),
+            Err(payload) => Err(JoinError::Panicked(panic_message(payload))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::timer::sleep;
+    use crate::{Runtime, spawn_task};
+
+    #[test]
+    fn join_all_timeout_gives_up_on_a_slow_task() {
+        let _runtime = runtime(Runtime::new());
+        let tasks: Vec<_> = [10, 20, 5_000]
+            .into_iter()
+            .map(|millis| {
+                spawn_task(
+                    async move {
+                        sleep(Duration::from_millis(millis)).await;
+                        millis
+                    },
+                    FutureType::High,
+                )
+            })
+            .collect();
+        let started = Instant::now();
+        let joined = future::block_on(join_all_timeout(tasks, Duration::from_millis(200)));
+        assert_eq!(joined, Err(Elapsed));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn join_all_timeout_returns_everything_in_time() {
+        let _runtime = runtime(Runtime::new());
+        let tasks: Vec<_> = [10, 20, 30]
+            .into_iter()
+            .map(|millis| {
+                spawn_task(
+                    async move {
+                        sleep(Duration::from_millis(millis)).await;
+                        millis
+                    },
+                    FutureType::Low,
+                )
+            })
+            .collect();
+        let joined = future::block_on(join_all_timeout(tasks, Duration::from_secs(5)));
+        assert_eq!(joined, Ok(vec![10, 20, 30]));
+    }
+
+    #[test]
+    fn map_concurrent_keeps_order_and_the_cap() {
+        let _runtime = runtime(Runtime::new());
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let mapped = {
+            let (running, most) = (running.clone(), most.clone());
+            map_concurrent(
+                (0..12u64).collect(),
+                3,
+                move |item| {
+                    let (running, most) = (running.clone(), most.clone());
+                    async move {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        most.fetch_max(now, Ordering::SeqCst);
+                        // later items finish sooner, so completion order isn't input order
+                        sleep(Duration::from_millis(24 - 2 * item)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        item * 2
+                    }
+                },
+                FutureType::Low,
+            )
+        };
+        let outputs = future::block_on(mapped);
+        assert_eq!(outputs, (0..12).map(|item| item * 2).collect::<Vec<_>>());
+        assert_eq!(most.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn join_next_timeout_times_out_early_and_returns_later() {
+        let _runtime = runtime(Runtime::new());
+        let mut set = JoinSet::new();
+        for millis in [200, 100] {
+            set.spawn(
+                async move {
+                    sleep(Duration::from_millis(millis)).await;
+                    millis
+                },
+                FutureType::High,
+            );
+        }
+        future::block_on(async {
+            let short = Duration::from_millis(20);
+            assert_eq!(set.join_next_timeout(short).await, Err(Elapsed));
+            assert_eq!(set.join_next_timeout(short).await, Err(Elapsed));
+            // nothing was lost to the timeouts
+            assert_eq!(set.len(), 2);
+            let long = Duration::from_secs(5);
+            assert_eq!(set.join_next_timeout(long).await, Ok(Some(100)));
+            assert_eq!(set.join_next_timeout(long).await, Ok(Some(200)));
+            assert_eq!(set.join_next_timeout(long).await, Ok(None));
+        });
+    }
+
+    #[test]
+    fn fan_out_hands_the_input_to_every_handler() {
+        let _runtime = runtime(Runtime::new());
+        let handlers: Vec<_> = [1, 10, 100]
+            .into_iter()
+            .map(|factor| {
+                move |input: Arc<Vec<u64>>| {
+                    spawn_task(
+                        async move { input.iter().sum::<u64>() * factor },
+                        FutureType::Low,
+                    )
+                }
+            })
+            .collect();
+        let input = Arc::new(vec![1, 2, 3]);
+        let outputs = future::block_on(fan_out(input.clone(), handlers));
+        assert_eq!(outputs, [6, 60, 600]);
+        // every clone handed out was dropped with its handler
+        assert_eq!(Arc::strong_count(&input), 1);
+    }
+
+    #[test]
+    fn parallel_map_keeps_input_order_with_a_chunk_per_worker() {
+        let _runtime = runtime(Runtime::new().with_high_num(3).with_low_num(1));
+        let squares = parallel_map((0..1_000u64).collect(), |i| i * i);
+        assert_eq!(squares, (0..1_000).map(|i| i * i).collect::<Vec<_>>());
+        #[cfg(feature = "metrics")]
+        assert_eq!(Runtime::take_metrics().spawned(), 3);
+        // fewer items than a chunk per worker would need
+        let words = parallel_map(vec!["a", "bb"], str::len);
+        assert_eq!(words, [1, 2]);
+        assert!(parallel_map(Vec::<u8>::new(), |i| i).is_empty());
+    }
+
+    #[test]
+    fn join_turns_panics_and_cancellation_into_errors() {
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let cancelled = spawn_task(async { 1 }, FutureType::Low);
+        // dropping a drained task cancels it
+        drop(Runtime::drain_queued());
+        assert_eq!(
+            future::block_on(cancelled.join()),
+            Err(JoinError::Cancelled)
+        );
+
+        Runtime::graceful_restart(Runtime::new());
+        let input = 3;
+        let panicked = spawn_task(
+            async move {
+                if input > 2 {
+                    panic!("bad input {input}");
+                }
+                input
+            },
+            FutureType::High,
+        );
+        let joined = future::block_on(panicked.join());
+        assert_eq!(joined, Err(JoinError::Panicked("bad input 3".to_string())));
+        let fine = spawn_task(async { 2 }, FutureType::High);
+        assert_eq!(future::block_on(fine.join()), Ok(2));
+    }
+
+    #[test]
+    fn join_last_returns_the_slower_output() {
+        let _runtime = runtime(Runtime::new());
+        let after = |millis: u64, value: &'static str| {
+            spawn_task(
+                async move {
+                    sleep(Duration::from_millis(millis)).await;
+                    value
+                },
+                FutureType::High,
+            )
+        };
+        let started = Instant::now();
+        let last = future::block_on(join_last(after(60, "slow"), after(10, "fast")));
+        assert_eq!(last, "slow");
+        assert!(started.elapsed() >= Duration::from_millis(60));
+        // whichever side it is on
+        let last = future::block_on(join_last(after(10, "fast"), after(60, "slow")));
+        assert_eq!(last, "slow");
+        // a tie goes to `b`
+        assert_eq!(
+            future::block_on(join_last(async { "a" }, async { "b" })),
+            "b"
+        );
+    }
+}
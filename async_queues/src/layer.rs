@@ -0,0 +1,136 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+// A spawned future with its output set aside, which is what layers get to wrap
+pub type BoxedTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// Wraps every spawned future, like a tower layer wraps a service, e.g. to time it, trace it
+// or bound it with a timeout. Closures taking and returning a BoxedTask are layers too. A
+// layer that drops the task's future before it finishes (a timeout firing, say) fails the
+// task, awaiting its Task panics.
+pub trait Layer: Send + Sync + 'static {
+    fn layer(&self, task: BoxedTask) -> BoxedTask;
+}
+
+impl<F> Layer for F
+where
+    F: Fn(BoxedTask) -> BoxedTask + Send + Sync + 'static,
+{
+    fn layer(&self, task: BoxedTask) -> BoxedTask {
+        self(task)
+    }
+}
+
+pub(crate) type Layers = Vec<Arc<dyn Layer>>;
+
+// Set through Runtime::with_layer, in the order they were added
+static LAYERS: RwLock<Layers> = RwLock::new(Vec::new());
+// whether LAYERS has any, so spawns without layers don't take the lock to find out
+static ANY: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn set_layers(layers: Layers) {
+    let mut current = LAYERS.write().unwrap();
+    ANY.store(!layers.is_empty(), Ordering::Release);
+    *current = layers;
+}
+
+// `future` with every layer around it, the first one added outermost, or the future itself
+// back if there are no layers
+pub(crate) fn apply<F, T>(future: F) -> Result<impl Future<Output = T> + Send + 'static, F>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    if !ANY.load(Ordering::Acquire) {
+        return Err(future);
+    }
+    // cloned so a layer that spawns doesn't take the lock again
+    let layers = {
+        let layers = LAYERS.read().unwrap();
+        if layers.is_empty() {
+            return Err(future);
+        }
+        layers.clone()
+    };
+    let output = Arc::new(Mutex::new(None));
+    let slot = output.clone();
+    let task: BoxedTask = Box::pin(async move {
+        *slot.lock().unwrap() = Some(future.await);
+    });
+    let task = layers
+        .iter()
+        .rev()
+        .fold(task, |task, layer| layer.layer(task));
+    Ok(async move {
+        task.await;
+        let output = output.lock().unwrap().take();
+        output.expect("a layer dropped the task's future before it finished")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{
+        FutureType, Runtime, join_all, spawn_batch, spawn_named_task, spawn_task, spawn_with_labels,
+    };
+
+    #[test]
+    fn layers_wrap_every_spawn() {
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let trace = Arc::new(Mutex::new(Vec::new()));
+        let counting = {
+            let spawned = spawned.clone();
+            move |task: BoxedTask| -> BoxedTask {
+                spawned.fetch_add(1, Ordering::SeqCst);
+                task
+            }
+        };
+        let tracing = |name: &'static str| {
+            let trace = trace.clone();
+            move |task: BoxedTask| -> BoxedTask {
+                let trace = trace.clone();
+                Box::pin(async move {
+                    trace.lock().unwrap().push(name);
+                    task.await
+                })
+            }
+        };
+        // one worker, so each task's trace entries end up next to each other
+        let _runtime = runtime(
+            Runtime::new()
+                .with_high_num(1)
+                .with_low_num(0)
+                .with_layer(counting)
+                .with_layer(tracing("outer"))
+                .with_layer(tracing("inner")),
+        );
+        let mut tasks = vec![
+            spawn_task(async { 1 }, FutureType::High),
+            spawn_named_task("named", async { 2 }, FutureType::Low),
+            spawn_with_labels(async { 3 }, FutureType::Low, [("kind", "test")]),
+        ];
+        let batch = [(4, FutureType::High), (5, FutureType::Low)];
+        tasks.extend(spawn_batch(
+            batch
+                .into_iter()
+                .map(|(value, order)| (async move { value }, order))
+                .collect(),
+        ));
+        let outputs = futures_lite::future::block_on(join_all(tasks));
+        // the outputs get through the layers untouched
+        assert_eq!(outputs, [1, 2, 3, 4, 5]);
+        assert_eq!(spawned.load(Ordering::SeqCst), 5);
+        // the first layer added is the outermost
+        let trace = trace.lock().unwrap();
+        assert_eq!(trace.len(), 10);
+        for pair in trace.chunks(2) {
+            assert_eq!(pair, ["outer", "inner"]);
+        }
+    }
+}
@@ -0,0 +1,1622 @@
+use std::future::Future;
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Once};
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "task-registry")]
+use std::time::Instant;
+
+use async_task::Runnable;
+use flume::{Receiver, Sender};
+
+// Scheduling events compile down to nothing unless the `scheduler-log` feature is on
+macro_rules! sched_log {
+    ($variant:ident { $($field:ident: $value:expr),* $(,)? }) => {
+        #[cfg(feature = "scheduler-log")]
+        $crate::sched_log::emit(|at| $crate::sched_log::SchedulerEvent::$variant { at, $($field: $value),* });
+        // type-checked like the real thing, but never evaluated
+        #[cfg(not(feature = "scheduler-log"))]
+        if false { $(let _ = $value;)* }
+    };
+}
+
+mod backoff;
+mod bias;
+mod blocking;
+mod cancel;
+mod capacity;
+mod continuation;
+mod coop;
+mod daemon;
+mod dedup;
+mod detached;
+mod drain;
+mod exec_order;
+mod generator;
+mod handle;
+mod health;
+mod idle;
+mod inline;
+mod join;
+mod layer;
+mod lifo;
+mod log_context;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod panics;
+mod progress;
+mod rate_limit;
+mod registry;
+mod resource;
+mod retry;
+mod rng;
+#[cfg(feature = "scheduler-log")]
+mod sched_log;
+mod scope;
+mod spill;
+mod stream;
+mod supervise;
+mod sync;
+mod tenant;
+#[cfg(test)]
+mod test_support;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+mod timer;
+mod watchdog;
+mod workers;
+
+pub use bias::WorkerBias;
+pub use cancel::{
+    CancelHandle, CancellationToken, Cancelled, WithCancellation, spawn_cancellable,
+    with_cancellation,
+};
+pub use coop::{YieldNow, consume_budget, yield_now};
+pub use dedup::{SharedTask, spawn_deduplicated};
+pub use detached::detach;
+pub use drain::PendingTask;
+pub use generator::{Generator, Yielder, spawn_generator};
+pub use handle::ExecutorHandle;
+pub use health::{Health, HealthIssue};
+pub use join::{
+    JoinAll, JoinError, JoinSet, TaskExt, fan_out, join_all, join_all_timeout, join_last,
+    map_concurrent, parallel_map,
+};
+pub use layer::{BoxedTask, Layer};
+pub use lifo::SpawnOrder;
+pub use log_context::{log_context, set_log_context};
+#[cfg(feature = "metrics")]
+pub use metrics::{LabelMetrics, Metrics};
+pub use panics::PanicPolicy;
+pub use progress::{ProgressReceiver, ProgressSender, spawn_with_progress};
+pub use rate_limit::{SpawnLimiter, spawn_rate_limited};
+pub use registry::{TaskId, TaskRecord};
+#[cfg(feature = "task-registry")]
+pub use registry::{TaskInfo, TaskState};
+pub use resource::with_resource;
+pub use retry::{RetryBudget, RetryPolicy, retry};
+#[cfg(feature = "scheduler-log")]
+pub use sched_log::{Labels, SchedulerEvent};
+pub use scope::{Scope, scope};
+pub use spill::{SerializableTask, SpillQueue};
+pub use stream::{Merge, merge, stream_from_iter};
+pub use supervise::{RestartPolicy, Supervised, spawn_supervised};
+pub use sync::{AsyncCondvar, AsyncMutex, AsyncMutexGuard, LockFuture};
+pub use tenant::{Tenant, spawn_for_tenant};
+pub use timer::{
+    Elapsed, Heartbeat, Sleep, Timeout, sleep, sleep_until, timeout, timeout_or_else,
+    with_heartbeat,
+};
+pub use watchdog::{BlockingDiagnostic, PollTimeout, Stalled, poll_timeout};
+pub use workers::spawn_when_capacity;
+
+// Every task carries its registry record as metadata, so workers and schedule closures can
+// reach it straight from the runnable
+pub type Task<T> = async_task::Task<T, TaskRecord>;
+pub type TaskRunnable = Runnable<TaskRecord>;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use futures_lite::future::block_on;
+
+    use crate::{FutureType, Task};
+    use std::future::Future;
+
+    // Backs the one-argument spawn_task! form so the implicit Low priority shows up as a
+    // deprecation warning at the call site
+    #[deprecated(
+        since = "0.1.0",
+        note = "spawn_task!(future) silently runs as FutureType::Low; pass the priority explicitly: spawn_task!(future, FutureType::Low)"
+    )]
+    #[track_caller]
+    pub fn spawn_task_implicit_low<F, T>(future: F) -> Task<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        crate::spawn_task(future, FutureType::Low)
+    }
+}
+
+// Creating our own macro for spawing task so that developer does not stress over the order.
+// Always pass the priority: the one-argument form still defaults to FutureType::Low but is
+// deprecated, since an accidental low priority is easy to miss and can starve behind high work.
+#[macro_export]
+macro_rules! spawn_task {
+    ($future:expr) => {
+        $crate::__private::spawn_task_implicit_low($future)
+    };
+    ($future:expr, $order:expr) => {
+        $crate::spawn_task($future, $order)
+    };
+}
+
+// creating our own join macro
+#[macro_export]
+macro_rules! join {
+    ($($future:expr),*) => {
+        vec![$($crate::__private::block_on($future)),*]
+    }
+}
+
+// join! blocks the calling thread, which is fine from main but stalls a worker if used
+// inside a task. A macro can't tell which context it was expanded in, so the async flavour
+// is a separate macro: async_join! polls all the futures together and has to be awaited.
+// At the top level wrap it in block_on, or keep using join!.
+#[macro_export]
+macro_rules! async_join {
+    ($($future:expr),* $(,)?) => {
+        $crate::join_all(vec![$(
+            ::std::boxed::Box::pin($future)
+                as ::std::pin::Pin<::std::boxed::Box<dyn ::std::future::Future<Output = _> + Send>>
+        ),*])
+    }
+}
+
+// Error can occur when joining
+// so we'll create try_join macro to handle error. A task that panicked comes back as Err,
+// under PanicPolicy::CatchAndFail with the task's own panic payload.
+#[macro_export]
+macro_rules! try_join {
+    ($($future:expr),*) => {
+        vec![$(::std::panic::catch_unwind(|| $crate::__private::block_on($future))),*]
+    }
+}
+
+// Set once Runtime::run has started the pools
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+// creating runtime
+pub struct Runtime {
+    high_num: usize,
+    low_num: usize,
+    rng_seed: Option<u64>,
+    timer_resolution: Duration,
+    inline_polls: usize,
+    slow_poll_threshold: Option<Duration>,
+    single_tier: Option<FutureType>,
+    max_spawn_depth: Option<u32>,
+    coop_budget: Option<u32>,
+    task_ttl: Option<Duration>,
+    high_bias: WorkerBias,
+    low_bias: WorkerBias,
+    weights: Option<(u32, u32)>,
+    park_when_idle: bool,
+    stealing: bool,
+    order_recording: bool,
+    continuation_priority: bool,
+    daemon_workers: bool,
+    panic_policy: PanicPolicy,
+    high_capacity: Option<usize>,
+    low_capacity: Option<usize>,
+    queue_full: Option<capacity::QueueFullCallback>,
+    layers: layer::Layers,
+    #[cfg(feature = "scheduler-log")]
+    scheduler_log: Option<sched_log::Sink>,
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        let num_cores = std::thread::available_parallelism().unwrap().get();
+        Self {
+            high_num: num_cores.saturating_sub(2).max(1),
+            low_num: 1,
+            rng_seed: None,
+            timer_resolution: timer::DEFAULT_RESOLUTION,
+            inline_polls: 0,
+            slow_poll_threshold: None,
+            single_tier: None,
+            max_spawn_depth: None,
+            coop_budget: None,
+            task_ttl: None,
+            high_bias: WorkerBias::default_for(FutureType::High),
+            low_bias: WorkerBias::default_for(FutureType::Low),
+            weights: None,
+            park_when_idle: false,
+            stealing: true,
+            order_recording: false,
+            continuation_priority: false,
+            daemon_workers: true,
+            panic_policy: PanicPolicy::default(),
+            high_capacity: None,
+            low_capacity: None,
+            queue_full: None,
+            layers: Vec::new(),
+            #[cfg(feature = "scheduler-log")]
+            scheduler_log: None,
+        }
+    }
+
+    // A runtime with only the low pool, `workers` threads, for batch processing with no
+    // interactive work. Every spawn goes to that pool whatever FutureType it asks for, so
+    // there is no second pool to fall back to.
+    pub fn batch_only(workers: usize) -> Self {
+        Self {
+            high_num: 0,
+            low_num: workers,
+            single_tier: Some(FutureType::Low),
+            ..Self::new()
+        }
+    }
+
+    // Same as batch_only but with everything on the high pool
+    pub fn interactive_only(workers: usize) -> Self {
+        Self {
+            high_num: workers,
+            low_num: 0,
+            single_tier: Some(FutureType::High),
+            ..Self::new()
+        }
+    }
+
+    // One high worker and one low worker, each taking only from its own queue, so every task
+    // runs on its priority's thread in the order it was queued and nothing depends on who
+    // gets to a task first. Execution order is recorded, see take_execution_order. Meant for
+    // tests that need to pin down ordering.
+    pub fn deterministic_pair() -> Self {
+        Self {
+            high_num: 1,
+            low_num: 1,
+            stealing: false,
+            order_recording: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn with_high_num(mut self, num: usize) -> Self {
+        self.high_num = num;
+        self
+    }
+    pub fn with_low_num(mut self, num: usize) -> Self {
+        self.low_num = num;
+        self
+    }
+
+    // Seed for the runtime's random decisions. Every randomized scheduling policy draws from
+    // one generator, which this resets, so the same seed gives the same sequence of picks and,
+    // on a runtime with a single worker, the same spawns run in the same order every time, e.g.
+    // to replay a scheduling bug. Random by default.
+    pub fn with_rng_seed(mut self, seed: u64) -> Self {
+        self.rng_seed = Some(seed);
+        self
+    }
+
+    // Which queue the workers of `pool` check first. By default high workers check high then
+    // low and low workers low then high; e.g. `with_worker_bias(FutureType::Low,
+    // WorkerBias::Weighted { high: 0.1 })` keeps low workers mostly on low work while they
+    // still grab a high task now and then.
+    pub fn with_worker_bias(mut self, pool: FutureType, bias: WorkerBias) -> Self {
+        match pool {
+            FutureType::High => self.high_bias = bias,
+            FutureType::Low => self.low_bias = bias,
+        }
+        self
+    }
+
+    // Serve the two queues in proportion `high:low` instead of by priority, e.g.
+    // `with_weights(3, 1)` takes three high tasks for every low one across all workers while
+    // both have work. An empty queue's turns go to the other one, so no worker idles next to
+    // queued work. Replaces with_worker_bias for both pools; a zero weight on both sides is
+    // the same as not setting any.
+    pub fn with_weights(mut self, high: u32, low: u32) -> Self {
+        self.weights = Some((high, low));
+        self
+    }
+
+    // Whether a worker whose own queue is empty may take from the other pool's. On by default;
+    // off, high work only ever runs on high workers and low work on low workers, and
+    // with_worker_bias and with_weights have no effect.
+    pub fn with_stealing(mut self, stealing: bool) -> Self {
+        self.stealing = stealing;
+        self
+    }
+
+    // Record the id of every task a worker picks up, in order, for take_execution_order. Off
+    // by default, it costs a lock per poll.
+    pub fn with_order_recording(mut self, recording: bool) -> Self {
+        self.order_recording = recording;
+        self
+    }
+
+    // Let idle workers block until a task is enqueued instead of also checking the queues
+    // every 100ms, so an idle runtime doesn't wake the CPU at all, e.g. on battery or in
+    // serverless environments. Enqueues wake a sleeping worker either way. Off by default.
+    pub fn with_park_when_idle(mut self, park: bool) -> Self {
+        self.park_when_idle = park;
+        self
+    }
+
+    // Run tasks that wake themselves up (wake_by_ref inside their own poll, e.g. to yield)
+    // ahead of freshly spawned ones on the same queue, so work that has already started gets
+    // finished before new work is picked up. Priorities still apply: a low continuation
+    // doesn't jump ahead of high spawns. Off by default, where a self-woken task goes to the
+    // back of its queue like any other.
+    pub fn with_continuation_priority(mut self, enabled: bool) -> Self {
+        self.continuation_priority = enabled;
+        self
+    }
+
+    // Whether the worker threads let the process exit under them. With daemon workers, the
+    // default, the process ends as soon as main returns (or process::exit is called) and tasks
+    // still queued or running are dropped mid-way. With `false`, exiting first blocks until
+    // every live task has finished, as Runtime::wait_idle does, so a background task that
+    // never completes keeps the process alive for good.
+    pub fn with_daemon_workers(mut self, daemon: bool) -> Self {
+        self.daemon_workers = daemon;
+        self
+    }
+
+    // How a panicking task is handled, on both pools alike; see PanicPolicy. CatchAndFail by
+    // default, which keeps every worker alive and hands the panic to whoever awaits the Task.
+    pub fn with_panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.panic_policy = policy;
+        self
+    }
+
+    // Treat `queue` as full once `capacity` tasks are waiting in it. The queues don't actually
+    // reject or block anything: a spawn onto a full queue still goes through, it just triggers
+    // the with_queue_full_callback callback. Unbounded by default.
+    pub fn with_queue_capacity(mut self, queue: FutureType, capacity: usize) -> Self {
+        match queue {
+            FutureType::High => self.high_capacity = Some(capacity),
+            FutureType::Low => self.low_capacity = Some(capacity),
+        }
+        self
+    }
+
+    // Called with the priority whenever a spawn finds its target queue at the capacity set by
+    // with_queue_capacity, e.g. to shed load or raise an alert. It runs on the spawning
+    // thread before the task is queued, so keep it short and don't spawn from it.
+    pub fn with_queue_full_callback(
+        mut self,
+        callback: impl Fn(FutureType) + Send + Sync + 'static,
+    ) -> Self {
+        self.queue_full = Some(Arc::new(callback));
+        self
+    }
+
+    // Wrap every task spawned from then on in `layer`, e.g. to count, time or trace them all
+    // without touching each spawn. Layers added first end up outermost. Scoped tasks aren't
+    // wrapped, as layers only take 'static futures.
+    pub fn with_layer(mut self, layer: impl Layer) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    // Minimum granularity of sleep/timeout deadlines, 1ms by default. Deadlines are rounded
+    // up to the next tick, so a coarse resolution makes timers fire late but never early,
+    // and saves the timer thread wake-ups.
+    pub fn with_timer_resolution(mut self, resolution: Duration) -> Self {
+        self.timer_resolution = resolution;
+        self
+    }
+
+    // Give every new task up to `polls` polls on the spawning thread before it goes to a
+    // worker, so short tasks finish without ever touching a queue. Off (0) by default.
+    // A task spawned from inside an inline poll is queued normally.
+    pub fn with_inline_polls(mut self, polls: usize) -> Self {
+        self.inline_polls = polls;
+        self
+    }
+
+    // Watch for tasks that block their worker: any single poll taking longer than `threshold`
+    // (like the demo's async_fn calling std::thread::sleep) is reported once per spawn site on
+    // stderr, and collected in Runtime::blocking_diagnostics. It only reports: the task keeps
+    // running where it is, and the fix belongs at the spawn site it points at.
+    pub fn with_slow_poll_watchdog(mut self, threshold: Duration) -> Self {
+        self.slow_poll_threshold = Some(threshold);
+        self
+    }
+
+    // Bound recursive spawning: a task spawned more than `depth` levels below a top-level task
+    // (one spawned from outside any task) isn't queued but run to completion right away on the
+    // spawning thread, so a recursive algorithm can't flood the queues. The spawning task, and
+    // the worker it is on, wait for that whole subtree meanwhile, which is unfair to everything
+    // else queued behind them. Unlimited by default.
+    pub fn with_max_spawn_depth(mut self, depth: u32) -> Self {
+        self.max_spawn_depth = Some(depth);
+        self
+    }
+
+    // Give every task a budget of `budget` operations per poll on the runtime's primitives:
+    // AsyncMutex::lock, AsyncCondvar::wait, ProgressReceiver::recv and consume_budget. Once it
+    // is spent they yield instead of completing, so a task looping over always-ready
+    // operations still lets the tasks queued behind it run, without an explicit yield_now.
+    // Unlimited by default.
+    pub fn with_coop_budget(mut self, budget: u32) -> Self {
+        self.coop_budget = Some(budget);
+        self
+    }
+
+    // Abort any task still around `ttl` after it was spawned, whether it is queued, running or
+    // waiting on something that never resolves. Its future is dropped at the next poll past the
+    // deadline (the deadline itself wakes it), and TaskExt::join reports JoinError::Ttl; awaiting
+    // the Task directly panics instead. spawn_with_ttl overrides it per task. None by default.
+    pub fn with_task_ttl(mut self, ttl: Duration) -> Self {
+        self.task_ttl = Some(ttl);
+        self
+    }
+
+    // Hand every scheduling decision (enqueue, dequeue, steal, park, unpark) to `sink`, with
+    // task ids and timestamps, e.g. `.with_scheduler_log(|event| eprintln!("{event}"))`.
+    // Needs the `scheduler-log` feature; without it none of this is compiled in.
+    #[cfg(feature = "scheduler-log")]
+    pub fn with_scheduler_log(
+        mut self,
+        sink: impl Fn(&SchedulerEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.scheduler_log = Some(Arc::new(sink));
+        self
+    }
+
+    pub fn run(&self) {
+        self.apply();
+        let high = spawn_task!(async {}, FutureType::High);
+        let low = spawn_task!(async {}, FutureType::Low);
+        join!(high, low);
+        STARTED.store(true, Ordering::Release);
+    }
+
+    // Switch the running process over to `config` without losing work, e.g. on a config
+    // reload. Every setting takes effect right away, and a fresh set of worker pools is started
+    // from `config`. The old workers finish the poll they are in and then exit; they take
+    // nothing new once they notice the switch, which an idle one does as soon as it is woken.
+    // The queues are shared, so tasks still queued are simply picked up by the new workers.
+    // During that window both sets of workers are polling, so up to old + new workers run at
+    // once. Nothing to retire before the first run: this is then the same as `config.run()`.
+    pub fn graceful_restart(config: Runtime) {
+        if !STARTED.load(Ordering::Acquire) {
+            config.run();
+            return;
+        }
+        config.apply();
+        let generation = GENERATION.fetch_add(1, Ordering::AcqRel) + 1;
+        start_workers(FutureType::High, config.high_num, generation);
+        start_workers(FutureType::Low, config.low_num, generation);
+        idle::wake_all();
+    }
+
+    // Publishes every setting; the pools pick up their sizes when they start
+    fn apply(&self) {
+        HIGH_NUM.store(self.high_num, Ordering::Relaxed);
+        LOW_NUM.store(self.low_num, Ordering::Relaxed);
+        rng::set_seed(self.rng_seed);
+        timer::set_resolution(self.timer_resolution);
+        timer::start();
+        inline::set_inline_polls(self.inline_polls);
+        watchdog::set_threshold(self.slow_poll_threshold);
+        set_single_tier(self.single_tier);
+        registry::set_max_depth(self.max_spawn_depth);
+        coop::set_budget(self.coop_budget);
+        registry::set_default_ttl(self.task_ttl);
+        bias::set(FutureType::High, self.high_bias);
+        bias::set(FutureType::Low, self.low_bias);
+        bias::set_weights(self.weights);
+        bias::set_stealing(self.stealing);
+        exec_order::set_recording(self.order_recording);
+        idle::set_park_when_idle(self.park_when_idle);
+        continuation::set_enabled(self.continuation_priority);
+        daemon::set_daemon(self.daemon_workers);
+        panics::set_policy(self.panic_policy);
+        capacity::set_capacity(FutureType::High, self.high_capacity);
+        capacity::set_capacity(FutureType::Low, self.low_capacity);
+        capacity::set_callback(self.queue_full.clone());
+        layer::set_layers(self.layers.clone());
+        #[cfg(feature = "scheduler-log")]
+        sched_log::set_sink(self.scheduler_log.clone());
+    }
+
+    // Drive `future` to completion on the calling thread, e.g. once per iteration of an
+    // application loop. The worker pools are process-wide: they are started by the first
+    // run/block_on (which runs this runtime's configuration if nothing did yet) and live until
+    // the process exits, so every later call reuses the same workers, and tasks detached
+    // during one call keep running into the next. Timers don't depend on who is blocking: the
+    // timer thread started by run fires them, so a sleep or timeout awaited directly by
+    // `future` completes like one inside a task. There is no IO reactor to drive.
+    pub fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        if !STARTED.load(Ordering::Acquire) {
+            self.run();
+        }
+        futures_lite::future::block_on(future)
+    }
+
+    // Snapshot of every task that has been spawned and not yet completed or dropped,
+    // ordered by task id. Meant for debug endpoints when chasing a hang.
+    #[cfg(feature = "task-registry")]
+    pub fn live_tasks() -> Vec<TaskInfo> {
+        registry::live_tasks()
+    }
+
+    // A handle implementing the futures crate's Spawn trait, for libraries that take one
+    pub fn handle() -> ExecutorHandle {
+        ExecutorHandle
+    }
+
+    // Send every new spawn to `to` whatever priority it asks for, until the guard is dropped,
+    // e.g. to treat everything as low priority while overloaded. Tasks already spawned keep
+    // their queue, wake-ups included. Guards nest if dropped in reverse order.
+    pub fn demote_new_spawns(to: FutureType) -> DemoteGuard {
+        DemoteGuard {
+            previous: DEMOTED.swap(tier_code(Some(to)), Ordering::Relaxed),
+        }
+    }
+
+    // Shed stale work: cancel every task spawned before `cutoff` that is still sitting in a
+    // queue, e.g. requests that waited too long to be worth answering. Returns how many were
+    // cancelled. Running tasks and tasks waiting to be woken are left alone. A cancelled task is
+    // dropped when a worker dequeues it, so awaiting its Task panics like any cancelled task;
+    // await `task.fallible()` instead to get None.
+    #[cfg(feature = "task-registry")]
+    pub fn cancel_spawned_before(cutoff: Instant) -> usize {
+        registry::cancel_queued_before(cutoff)
+    }
+
+    // Take every task that is waiting in a queue and hasn't been picked up by a worker yet, so
+    // the work can be held back (e.g. while migrating) and resumed later with
+    // PendingTask::resume. Running tasks and tasks waiting to be woken carry on as usual.
+    pub fn drain_queued() -> Vec<PendingTask> {
+        drain::drain_queued()
+    }
+
+    // Ids of the tasks workers picked up since the last call, in the order they did, with one
+    // entry per poll. Empty unless with_order_recording (or deterministic_pair) is on.
+    pub fn take_execution_order() -> Vec<TaskId> {
+        exec_order::take()
+    }
+
+    // Whether the workers have nothing to do right now: both queues are empty and no task is
+    // being polled. Tasks waiting to be woken, e.g. on a timer, don't count, so this can be
+    // true while wait_idle would still block.
+    pub fn is_idle() -> bool {
+        queued(FutureType::High) == 0 && queued(FutureType::Low) == 0 && workers::in_flight() == 0
+    }
+
+    // Block until no task is left at all: none queued, running or waiting to be woken. Tasks
+    // spawned while waiting are waited for too. Call it from outside the runtime, a task
+    // waiting for itself never returns.
+    pub fn wait_idle() {
+        registry::wait_idle()
+    }
+
+    // Resolves once every task in `ids` has finished or been cancelled, e.g. for tasks whose
+    // handles were detached but whose ids were kept, say from live_tasks
+    #[cfg(feature = "task-registry")]
+    pub fn join_ids(ids: Vec<TaskId>) -> impl Future<Output = ()> {
+        registry::join_ids(ids)
+    }
+
+    // Block until every task handed to `detach` has finished
+    pub fn wait_detached() {
+        detached::wait()
+    }
+
+    // Async flavour of wait_detached, for use from inside a task
+    pub fn join_detached() -> impl Future<Output = ()> {
+        detached::join()
+    }
+
+    // Worker threads running in the given pool
+    pub fn worker_count(pool: FutureType) -> usize {
+        workers::count(pool)
+    }
+
+    // Snapshot of the queues, workers and counters. Reading changes nothing, so any number of
+    // callers can look: utilization and the counters cover the time since the last take_metrics.
+    #[cfg(feature = "metrics")]
+    pub fn metrics() -> Metrics {
+        metrics::snapshot()
+    }
+
+    // Same as metrics, but the counters and the utilization window also start over, so each
+    // reporting interval gets just its own events instead of a running total to diff. Call it
+    // from one place on a fixed interval, e.g. the loop deciding whether to add or remove
+    // workers.
+    #[cfg(feature = "metrics")]
+    pub fn take_metrics() -> Metrics {
+        metrics::take()
+    }
+
+    // Liveness summary for readiness probes: Degraded if a worker has been stuck in one poll
+    // past the with_slow_poll_watchdog threshold (1s if that's off; metrics feature only), a
+    // queue is at the capacity set with with_queue_capacity, or any task panicked since the
+    // previous call. Call it from one place on a fixed interval so the panic count covers one
+    // interval each time.
+    pub fn health() -> Health {
+        health::check()
+    }
+
+    // Thread dump for tasks: prints a line to stderr for every task that hasn't finished a poll
+    // for longer than the with_slow_poll_watchdog threshold (1s if that's off), with its id,
+    // name, state, spawn location, labels and when it was last polled, and returns the same text.
+    // Meant for when wait_idle or a join hangs, to find who is waiting on what.
+    #[cfg(feature = "task-registry")]
+    pub fn dump_stuck_tasks() -> String {
+        let dump = health::dump_stuck_tasks();
+        eprint!("{dump}");
+        dump
+    }
+
+    // Spawn sites caught blocking a worker since with_slow_poll_watchdog was turned on
+    pub fn blocking_diagnostics() -> Vec<BlockingDiagnostic> {
+        watchdog::diagnostics()
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) static HIGH_CHANNEL: LazyLock<(Sender<TaskRunnable>, Receiver<TaskRunnable>)> =
+    LazyLock::new(flume::unbounded::<TaskRunnable>);
+pub(crate) static LOW_CHANNEL: LazyLock<(Sender<TaskRunnable>, Receiver<TaskRunnable>)> =
+    LazyLock::new(flume::unbounded::<TaskRunnable>);
+// Lazy initialization
+// The QUEUE is a sender end of a channel, initialized once. It spawns the pool's background
+// threads, which loop recieving Runnable's and running them
+pub(crate) static HIGHQUEUE: LazyLock<flume::Sender<TaskRunnable>> = LazyLock::new(|| {
+    start_workers(FutureType::High, pool_size(&HIGH_NUM), 0);
+    HIGH_CHANNEL.0.clone()
+});
+pub(crate) static LOWQUEUE: LazyLock<flume::Sender<TaskRunnable>> = LazyLock::new(|| {
+    start_workers(FutureType::Low, pool_size(&LOW_NUM), 0);
+    LOW_CHANNEL.0.clone()
+});
+
+// Bumped by Runtime::graceful_restart; workers of an older generation retire
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+fn start_workers(pool: FutureType, count: usize, generation: usize) {
+    for _ in 0..count {
+        let worker = workers::started(pool);
+        thread::spawn(move || worker_loop(pool, worker, generation));
+    }
+}
+
+// Pool sizes published by Runtime::run, unset (usize::MAX) until then
+static HIGH_NUM: AtomicUsize = AtomicUsize::new(usize::MAX);
+static LOW_NUM: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+// Spawning before any runtime was configured falls back to Runtime::new()'s defaults
+// (core-count-based) so quick scripts just work, with a one-time warning pointing at the
+// explicit setup.
+fn pool_size(size: &AtomicUsize) -> usize {
+    static FALLBACK: Once = Once::new();
+    if size.load(Ordering::Relaxed) == usize::MAX {
+        FALLBACK.call_once(|| {
+            let runtime = Runtime::new();
+            eprintln!(
+                "async_queues: task spawned without a configured runtime, falling back to \
+                 the defaults ({} high / {} low workers); call Runtime::new().run() first to \
+                 choose them",
+                runtime.high_num, runtime.low_num
+            );
+            // a runtime configured meanwhile keeps its own sizes
+            let fill_in = |size: &AtomicUsize, default| {
+                let _ = size.compare_exchange(
+                    usize::MAX,
+                    default,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
+            };
+            fill_in(&HIGH_NUM, runtime.high_num);
+            fill_in(&LOW_NUM, runtime.low_num);
+        });
+    }
+    size.load(Ordering::Relaxed)
+}
+
+fn receiver(queue: FutureType) -> &'static Receiver<TaskRunnable> {
+    match queue {
+        FutureType::High => &HIGH_CHANNEL.1,
+        FutureType::Low => &LOW_CHANNEL.1,
+    }
+}
+
+// Next task from `queue`: its continuations first, then its Lifo stack, then the channel
+fn take(queue: FutureType) -> Option<TaskRunnable> {
+    continuation::pop(queue)
+        .or_else(|| lifo::pop(queue))
+        .or_else(|| receiver(queue).try_recv().ok())
+}
+
+// Tasks waiting in `queue`, continuations and Lifo ones included
+pub(crate) fn queued(queue: FutureType) -> usize {
+    receiver(queue).len() + lifo::len(queue) + continuation::len(queue)
+}
+
+// Each pass probes the queue picked by the pool's WorkerBias first and the other one second.
+// Taking from the pool's own queue is a dequeue, taking from the other one a steal. With
+// stealing off, only the own queue is probed.
+fn worker_loop(pool: FutureType, worker: usize, generation: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::register(worker);
+    let _exit = workers::Exit {
+        pool,
+        #[cfg(feature = "metrics")]
+        worker,
+    };
+    let mut parked = false;
+    loop {
+        if GENERATION.load(Ordering::Acquire) != generation {
+            // it may have been woken for a task it is leaving to the others
+            idle::wake_all();
+            return;
+        }
+        let epoch = idle::epoch();
+        let (first, second) = if bias::stealing() {
+            let first = bias::first_queue(pool);
+            let second = match first {
+                FutureType::High => FutureType::Low,
+                FutureType::Low => FutureType::High,
+            };
+            (first, Some(second))
+        } else {
+            (pool, None)
+        };
+        let Some((runnable, queue)) = take(first)
+            .map(|runnable| (runnable, first))
+            .or_else(|| second.and_then(|second| take(second).map(|runnable| (runnable, second))))
+        else {
+            park(worker, &mut parked);
+            idle::wait(epoch);
+            continue;
+        };
+
+        // idle::notify wakes one sleeper per burst of enqueues, so the first one up passes the
+        // wake-up on while there is more work, and so on until every worker is busy
+        if parked && queued(FutureType::High) + queued(FutureType::Low) > 0 {
+            idle::notify();
+        }
+        unpark(worker, &mut parked);
+        // dropping the runnable drops the future, which is what cancels the task
+        if !runnable.metadata().claim() {
+            continue;
+        }
+        exec_order::record(runnable.metadata().id());
+        // both arms are empty without the scheduler-log feature
+        #[allow(clippy::if_same_then_else)]
+        if queue == pool {
+            sched_log!(Dequeue {
+                task: runnable.metadata().id(),
+                labels: runnable.metadata().labels(),
+                worker: worker,
+                queue: queue,
+            });
+        } else {
+            sched_log!(Steal {
+                task: runnable.metadata().id(),
+                labels: runnable.metadata().labels(),
+                worker: worker,
+                from: queue,
+            });
+        }
+        panics::run(|| workers::run(runnable));
+    }
+}
+
+// A worker counts as parked from the first empty poll of both queues until it finds work
+// again, so the log gets one park/unpark pair per idle stretch rather than one every 100ms
+fn park(worker: usize, parked: &mut bool) {
+    if !*parked {
+        *parked = true;
+        sched_log!(Park { worker: worker });
+    }
+}
+
+fn unpark(worker: usize, parked: &mut bool) {
+    if *parked {
+        *parked = false;
+        sched_log!(Unpark { worker: worker });
+    }
+}
+
+// The schedule function sends runnable to the queue, which the background thread picks up.
+// Marking the record as queued here keeps `live_tasks` honest about where the task is.
+fn schedule_high(runnable: TaskRunnable) {
+    schedule_on(runnable, FutureType::High, SpawnOrder::Fifo)
+}
+fn schedule_low(runnable: TaskRunnable) {
+    schedule_on(runnable, FutureType::Low, SpawnOrder::Fifo)
+}
+fn schedule_high_lifo(runnable: TaskRunnable) {
+    schedule_on(runnable, FutureType::High, SpawnOrder::Lifo)
+}
+fn schedule_low_lifo(runnable: TaskRunnable) {
+    schedule_on(runnable, FutureType::Low, SpawnOrder::Lifo)
+}
+
+fn schedule_on(runnable: TaskRunnable, queue: FutureType, order: SpawnOrder) {
+    let Some(runnable) = inline::capture(runnable) else {
+        return;
+    };
+    #[cfg(feature = "task-registry")]
+    runnable.metadata().set_state(TaskState::Queued);
+    sched_log!(Enqueue {
+        task: runnable.metadata().id(),
+        labels: runnable.metadata().labels(),
+        queue: queue
+    });
+    // going through the sender also starts the pool if nothing did yet
+    let sender = match queue {
+        FutureType::High => &*HIGHQUEUE,
+        FutureType::Low => &*LOWQUEUE,
+    };
+    if continuation::is_continuation(runnable.metadata().id()) {
+        continuation::push(queue, runnable);
+    } else {
+        match order {
+            SpawnOrder::Fifo => sender.send(runnable).unwrap(),
+            SpawnOrder::Lifo => lifo::push(queue, runnable),
+        }
+    }
+    #[cfg(feature = "metrics")]
+    metrics::enqueued(queue);
+    idle::notify();
+}
+
+// Set by batch_only/interactive_only: 0 keeps the requested priority, otherwise every spawn
+// is sent to that one pool
+static SINGLE_TIER: AtomicU8 = AtomicU8::new(0);
+
+// Set while a DemoteGuard is held, same encoding as SINGLE_TIER
+static DEMOTED: AtomicU8 = AtomicU8::new(0);
+
+fn tier_code(tier: Option<FutureType>) -> u8 {
+    match tier {
+        None => 0,
+        Some(FutureType::High) => 1,
+        Some(FutureType::Low) => 2,
+    }
+}
+
+fn set_single_tier(tier: Option<FutureType>) {
+    SINGLE_TIER.store(tier_code(tier), Ordering::Relaxed);
+}
+
+// The queue a spawn actually lands on. A single tier runtime has no other pool, so it beats
+// a demotion.
+fn route(order: FutureType) -> FutureType {
+    router()(order)
+}
+
+// Same as route, with the routing read once for a whole batch of spawns
+fn router() -> impl Fn(FutureType) -> FutureType {
+    let tier = match SINGLE_TIER.load(Ordering::Relaxed) {
+        0 => DEMOTED.load(Ordering::Relaxed),
+        tier => tier,
+    };
+    move |order| match tier {
+        1 => FutureType::High,
+        2 => FutureType::Low,
+        _ => order,
+    }
+}
+
+// Returned by Runtime::demote_new_spawns; routing goes back to what it was when it is dropped
+#[must_use = "new spawns are only redirected while the guard is held"]
+pub struct DemoteGuard {
+    previous: u8,
+}
+
+impl Drop for DemoteGuard {
+    fn drop(&mut self) {
+        DEMOTED.store(self.previous, Ordering::Relaxed);
+    }
+}
+
+// Creating a simple executor where tasks are queued and run on one thread.
+#[track_caller]
+pub fn spawn_task<F, T>(future: F, order: FutureType) -> Task<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    spawn(future, order, None, Location::caller())
+}
+
+// Spawn every future in `items` at its own priority, e.g. a fan-out mixing interactive and
+// background work. The Tasks come back in the same order as the futures. The whole batch is
+// routed and registered in one go, so it costs one trip through the registry lock rather than
+// one per task (with the task-registry feature), and a demotion starting halfway can't split it.
+#[track_caller]
+pub fn spawn_batch<F, T>(items: Vec<(F, FutureType)>) -> Vec<Task<T>>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let location = Location::caller();
+    let route = router();
+    if registry::too_deep() {
+        return items
+            .into_iter()
+            .map(|(future, order)| {
+                run_to_completion(future, route(order), None, Vec::new(), None, location)
+            })
+            .collect();
+    }
+    let built: Vec<_> = items
+        .into_iter()
+        .map(|(future, order)| {
+            let order = route(order);
+            let record = registry::new_record(None, Vec::new(), None, order, location);
+            let (runnable, task) = build(future, record, scheduler(order, SpawnOrder::Fifo));
+            (runnable, task, order)
+        })
+        .collect();
+    registry::register_batch(built.iter().map(|(runnable, ..)| runnable.metadata()));
+    built
+        .into_iter()
+        .map(|(runnable, task, order)| {
+            start(runnable, order);
+            task
+        })
+        .collect()
+}
+
+// Same as spawn_task, but the name shows up in `Runtime::live_tasks`
+#[track_caller]
+pub fn spawn_named_task<F, T>(name: impl Into<String>, future: F, order: FutureType) -> Task<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    spawn(future, order, Some(name.into()), Location::caller())
+}
+
+// Same as spawn_task, with key-value labels such as `[("tenant", "acme"), ("kind", "upload")]`
+// attached to the task. They are kept in its registry entry and listed in `Runtime::live_tasks`,
+// where TaskInfo::label looks one up, e.g. to count or inspect a tenant's tasks. Metrics::label
+// adds up what the tasks carrying a label did, and scheduler log events show their labels.
+#[track_caller]
+pub fn spawn_with_labels<F, T, K, V>(
+    future: F,
+    order: FutureType,
+    labels: impl IntoIterator<Item = (K, V)>,
+) -> Task<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+    K: Into<String>,
+    V: Into<String>,
+{
+    let labels = labels
+        .into_iter()
+        .map(|(key, value)| (key.into(), value.into()))
+        .collect();
+    spawn_ordered(
+        future,
+        order,
+        SpawnOrder::Fifo,
+        None,
+        labels,
+        None,
+        Location::caller(),
+    )
+}
+
+// Same as spawn_task, but the task is aborted once it has been around for `ttl`, instead of
+// after the runtime's with_task_ttl, if any
+#[track_caller]
+pub fn spawn_with_ttl<F, T>(future: F, order: FutureType, ttl: Duration) -> Task<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_ordered(
+        future,
+        order,
+        SpawnOrder::Fifo,
+        None,
+        Vec::new(),
+        Some(ttl),
+        Location::caller(),
+    )
+}
+
+// Spawn `body` as a task that only starts once `dependency` has completed, with its output.
+// Takes the dependency by value since its output gets moved into `body`; to fan one result
+// out to several followers, chain a single task that hands out clones.
+#[track_caller]
+pub fn spawn_after_task<A, B, F, Fut>(dependency: Task<A>, body: F, order: FutureType) -> Task<B>
+where
+    A: Send + 'static,
+    B: Send + 'static,
+    F: FnOnce(A) -> Fut + Send + 'static,
+    Fut: Future<Output = B> + Send + 'static,
+{
+    spawn(
+        async move { body(dependency.await).await },
+        order,
+        None,
+        Location::caller(),
+    )
+}
+
+// Same as spawn_task, but `spawn_order` says where in the queue the task lands, every time it
+// is scheduled: SpawnOrder::Lifo puts it ahead of everything queued so far, which runs trees of
+// recursively spawned tasks depth-first instead of level by level.
+#[track_caller]
+pub fn spawn_task_ordered<F, T>(future: F, order: FutureType, spawn_order: SpawnOrder) -> Task<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_ordered(
+        future,
+        order,
+        spawn_order,
+        None,
+        Vec::new(),
+        None,
+        Location::caller(),
+    )
+}
+
+// Escape hatch from the high/low routing: every time the task is woken, the first time
+// included, its runnable goes to `schedule`, which decides where and when it runs, e.g. to log
+// or delay it before passing it on with `enqueue`. The closure must
+// - eventually call `run()` on the runnable, or drop it, which cancels the task;
+// - not run it from inside the closure: a task that wakes itself would recurse;
+// - not block, as it is called from whichever thread wakes the task.
+// Such a task is never polled inline and is listed as Low priority in `Runtime::live_tasks`.
+#[track_caller]
+pub fn spawn_with_schedule<F, T, S>(future: F, schedule: S) -> Task<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+    S: Fn(TaskRunnable) + Send + Sync + 'static,
+{
+    #[cfg(feature = "task-registry")]
+    let schedule = move |runnable: TaskRunnable| {
+        runnable.metadata().set_state(TaskState::Queued);
+        schedule(runnable)
+    };
+    let record = registry::new_record(None, Vec::new(), None, FutureType::Low, Location::caller());
+    let (runnable, task) = build(future, record, schedule);
+    registry::register(runnable.metadata());
+    runnable.schedule();
+    task
+}
+
+// Put a runnable on one of the runtime's queues for the workers, the way a regular spawn at
+// that priority is scheduled. Meant for forwarding from a spawn_with_schedule closure.
+pub fn enqueue(runnable: TaskRunnable, queue: FutureType) {
+    match route(queue) {
+        FutureType::High => schedule_high(runnable),
+        FutureType::Low => schedule_low(runnable),
+    }
+}
+
+fn spawn<F, T>(
+    future: F,
+    order: FutureType,
+    name: Option<String>,
+    location: &'static Location<'static>,
+) -> Task<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_ordered(
+        future,
+        order,
+        SpawnOrder::Fifo,
+        name,
+        Vec::new(),
+        None,
+        location,
+    )
+}
+
+fn spawn_ordered<F, T>(
+    future: F,
+    order: FutureType,
+    spawn_order: SpawnOrder,
+    name: Option<String>,
+    labels: Vec<(String, String)>,
+    ttl: Option<Duration>,
+    location: &'static Location<'static>,
+) -> Task<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let order = route(order);
+    if registry::too_deep() {
+        return run_to_completion(future, order, name, labels, ttl, location);
+    }
+    let record = registry::new_record(name, labels, ttl, order, location);
+    let (runnable, task) = build(future, record, scheduler(order, spawn_order));
+    registry::register(runnable.metadata());
+    start(runnable, order);
+    task
+}
+
+// The schedule function of a task spawned onto `order`
+fn scheduler(order: FutureType, spawn_order: SpawnOrder) -> fn(TaskRunnable) {
+    // runnable.schedult() sends it initially to the queue.
+    match (order, spawn_order) {
+        (FutureType::High, SpawnOrder::Fifo) => schedule_high,
+        (FutureType::Low, SpawnOrder::Fifo) => schedule_low,
+        (FutureType::High, SpawnOrder::Lifo) => schedule_high_lifo,
+        (FutureType::Low, SpawnOrder::Lifo) => schedule_low_lifo,
+    }
+}
+
+// The rest of a spawn once the task is built and registered: queue it, or poll it right here
+// if inline polling is on
+fn start(runnable: TaskRunnable, order: FutureType) {
+    if let Some(runnable) = inline::run_inline(runnable) {
+        capacity::check(order);
+        runnable.schedule();
+    }
+}
+
+// For spawns past the max spawn depth: the task's wake-ups come back to this thread instead of
+// a queue, and it is polled here until done, so the returned Task is already finished
+fn run_to_completion<F, T>(
+    future: F,
+    order: FutureType,
+    name: Option<String>,
+    labels: Vec<(String, String)>,
+    ttl: Option<Duration>,
+    location: &'static Location<'static>,
+) -> Task<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = flume::unbounded();
+    let schedule = move |runnable: TaskRunnable| {
+        let _ = sender.send(runnable);
+    };
+    let record = registry::new_record(name, labels, ttl, order, location);
+    let (mut runnable, task) = build(future, record, schedule);
+    registry::register(runnable.metadata());
+    loop {
+        panics::run(|| {
+            runnable.run();
+        });
+        if task.is_finished() {
+            return task;
+        }
+        // the task holds the sender, and we hold the task
+        runnable = receiver.recv().unwrap();
+    }
+}
+
+// it wraps the future into a Runnable ( which polls it ) and a Task (handle).
+fn build<F, T, S>(future: F, record: TaskRecord, schedule: S) -> (TaskRunnable, Task<T>)
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+    S: Fn(TaskRunnable) + Send + Sync + 'static,
+{
+    let builder = async_task::Builder::new()
+        .metadata(record)
+        .propagate_panic(panics::captured_by_task());
+    // SAFETY: Tracked is Send and 'static but for the record it borrows, which is the task's
+    // own metadata, and async_task only drops that after the future
+    unsafe {
+        match layer::apply(future) {
+            Ok(future) => {
+                builder.spawn_unchecked(|record| registry::Tracked::new(future, record), schedule)
+            }
+            Err(future) => {
+                builder.spawn_unchecked(|record| registry::Tracked::new(future, record), schedule)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub enum FutureType {
+    High,
+    Low,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::test_support::{run_child, runtime, scenario};
+
+    #[test]
+    fn join_blocks_at_the_top_level() {
+        let _runtime = runtime(Runtime::new());
+        let one = spawn_task!(async { 1 }, FutureType::High);
+        let two = spawn_task!(async { 2 }, FutureType::Low);
+        assert_eq!(join!(one, two), [1, 2]);
+    }
+
+    #[test]
+    fn async_join_awaits_inside_a_task() {
+        let _runtime = runtime(Runtime::new());
+        let outer = spawn_task!(
+            async {
+                let one = spawn_task!(async { 1 }, FutureType::High);
+                let two = spawn_task!(async { 2 }, FutureType::Low);
+                async_join!(one, two, async { 3 }).await
+            },
+            FutureType::High
+        );
+        assert_eq!(futures_lite::future::block_on(outer), [1, 2, 3]);
+    }
+
+    // Every spawn goes to the one pool, whatever it asks for, and its workers run it: the
+    // other pool has none
+    fn assert_single_pool(pool: FutureType) {
+        assert_eq!(route(FutureType::High), pool);
+        assert_eq!(route(FutureType::Low), pool);
+        let tasks: Vec<_> = [FutureType::High, FutureType::Low]
+            .into_iter()
+            .map(|order| spawn_task(async {}, order))
+            .collect();
+        #[cfg(feature = "task-registry")]
+        for task in &tasks {
+            assert_eq!(task.metadata().info().priority, pool);
+        }
+        futures_lite::future::block_on(join_all(tasks));
+    }
+
+    #[test]
+    fn batch_only_runs_everything_on_the_low_pool() {
+        let _runtime = runtime(Runtime::batch_only(2));
+        assert_eq!(Runtime::worker_count(FutureType::High), 0);
+        assert_eq!(Runtime::worker_count(FutureType::Low), 2);
+        assert_single_pool(FutureType::Low);
+    }
+
+    #[test]
+    fn interactive_only_runs_everything_on_the_high_pool() {
+        let _runtime = runtime(Runtime::interactive_only(2));
+        assert_eq!(Runtime::worker_count(FutureType::High), 2);
+        assert_eq!(Runtime::worker_count(FutureType::Low), 0);
+        assert_single_pool(FutureType::High);
+    }
+
+    #[test]
+    fn spawning_without_a_runtime_uses_the_defaults() {
+        if scenario().as_deref() == Some("unconfigured") {
+            // a fresh process where nothing ran a Runtime
+            let one = spawn_task(async { 1 }, FutureType::High);
+            let two = spawn_task(async { 2 }, FutureType::Low);
+            let three = spawn_task(async { 3 }, FutureType::High);
+            let outputs = futures_lite::future::block_on(join_all([one, two, three]));
+            assert_eq!(outputs, [1, 2, 3]);
+            return;
+        }
+        let output = run_child(
+            "tests::spawning_without_a_runtime_uses_the_defaults",
+            "unconfigured",
+            Duration::from_secs(30),
+        )
+        .expect("the unconfigured process didn't finish");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{stderr}");
+        assert_eq!(
+            stderr.matches("without a configured runtime").count(),
+            1,
+            "{stderr}"
+        );
+    }
+
+    #[test]
+    fn dependent_task_runs_after_its_dependency_with_its_output() {
+        let _runtime = runtime(Runtime::new());
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let first = spawn_task(
+            {
+                let log = log.clone();
+                async move {
+                    timer::sleep(Duration::from_millis(30)).await;
+                    log.lock().unwrap().push("first");
+                    20
+                }
+            },
+            FutureType::Low,
+        );
+        let second = spawn_after_task(
+            first,
+            {
+                let log = log.clone();
+                move |value| async move {
+                    log.lock().unwrap().push("second");
+                    value + 1
+                }
+            },
+            FutureType::High,
+        );
+        assert_eq!(futures_lite::future::block_on(second), 21);
+        assert_eq!(*log.lock().unwrap(), ["first", "second"]);
+    }
+
+    #[test]
+    fn block_on_reuses_the_same_workers_every_call() {
+        let config = Runtime::new().with_high_num(2).with_low_num(1);
+        let _runtime = runtime(Runtime::new().with_high_num(2).with_low_num(1));
+        #[cfg(feature = "metrics")]
+        assert_eq!(Runtime::metrics().utilization().len(), 3);
+        for call in 0..3 {
+            let outputs = config.block_on(async move {
+                let tasks: Vec<_> = (0..4)
+                    .map(|i| spawn_task(async move { call * 10 + i }, FutureType::High))
+                    .collect();
+                join_all(tasks).await
+            });
+            assert_eq!(outputs, (0..4).map(|i| call * 10 + i).collect::<Vec<_>>());
+            // no worker threads were started, or lost, by the call
+            #[cfg(feature = "metrics")]
+            assert_eq!(Runtime::metrics().utilization().len(), 3);
+            assert_eq!(Runtime::worker_count(FutureType::High), 2);
+            assert_eq!(Runtime::worker_count(FutureType::Low), 1);
+        }
+    }
+
+    #[cfg(feature = "task-registry")]
+    #[test]
+    fn cancel_spawned_before_sheds_only_the_stale_tasks() {
+        // no workers yet, so everything stays queued until the cutoff has been applied
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let stale: Vec<_> = (0..3)
+            .map(|i| spawn_task(async move { i }, FutureType::High))
+            .collect();
+        thread::sleep(Duration::from_millis(5));
+        let cutoff = Instant::now();
+        thread::sleep(Duration::from_millis(5));
+        let fresh: Vec<_> = (3..5)
+            .map(|i| spawn_task(async move { i }, FutureType::Low))
+            .collect();
+        assert_eq!(Runtime::cancel_spawned_before(cutoff), 3);
+        Runtime::graceful_restart(Runtime::new());
+        for task in stale {
+            assert_eq!(futures_lite::future::block_on(task.fallible()), None);
+        }
+        let fresh: Vec<_> = fresh
+            .into_iter()
+            .map(|task| futures_lite::future::block_on(task.fallible()))
+            .collect();
+        assert_eq!(fresh, [Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn custom_schedule_sees_every_wake() {
+        let _runtime = runtime(Runtime::new());
+        let scheduled = Arc::new(AtomicUsize::new(0));
+        let task = spawn_with_schedule(
+            async {
+                for _ in 0..5 {
+                    yield_now().await;
+                }
+                "done"
+            },
+            {
+                let scheduled = scheduled.clone();
+                move |runnable| {
+                    scheduled.fetch_add(1, Ordering::Relaxed);
+                    enqueue(runnable, FutureType::High);
+                }
+            },
+        );
+        assert_eq!(futures_lite::future::block_on(task), "done");
+        // the first schedule, then one per time the future woke itself
+        assert_eq!(scheduled.load(Ordering::Relaxed), 6);
+    }
+
+    #[test]
+    fn block_on_drives_sleeps_from_a_fresh_process() {
+        if scenario().as_deref() == Some("fresh block_on") {
+            // nothing started the timer thread before this call
+            let output = Runtime::new().block_on(async {
+                timer::sleep(Duration::from_millis(20)).await;
+                let nested = spawn_task(
+                    async {
+                        timer::sleep(Duration::from_millis(20)).await;
+                        2
+                    },
+                    FutureType::Low,
+                );
+                nested.await * 3
+            });
+            assert_eq!(output, 6);
+            return;
+        }
+        let output = run_child(
+            "tests::block_on_drives_sleeps_from_a_fresh_process",
+            "fresh block_on",
+            Duration::from_secs(30),
+        )
+        .expect("block_on hung on a sleep");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[test]
+    fn graceful_restart_mid_workload_loses_no_tasks() {
+        let _runtime = runtime(Runtime::new().with_high_num(2).with_low_num(2));
+        let workload = |from: u64| -> Vec<_> {
+            (from..from + 100)
+                .map(|i| {
+                    let order = if i % 3 == 0 {
+                        FutureType::Low
+                    } else {
+                        FutureType::High
+                    };
+                    let work = async move {
+                        timer::sleep(Duration::from_millis(i % 5)).await;
+                        thread::sleep(Duration::from_micros(200));
+                        i
+                    };
+                    spawn_task(work, order)
+                })
+                .collect()
+        };
+        let mut tasks = workload(0);
+        thread::sleep(Duration::from_millis(5));
+        Runtime::graceful_restart(Runtime::new().with_high_num(1).with_low_num(1));
+        tasks.extend(workload(100));
+        let outputs = futures_lite::future::block_on(join_all(tasks));
+        assert_eq!(outputs, (0..200).collect::<Vec<_>>());
+        #[cfg(feature = "metrics")]
+        assert_eq!(Runtime::take_metrics().completed(), 200);
+
+        // once the old workers have gone, only the new ones are sampled
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Runtime::worker_count(FutureType::High) + Runtime::worker_count(FutureType::Low) > 2 {
+            assert!(Instant::now() < deadline, "the old workers didn't retire");
+            thread::sleep(Duration::from_millis(1));
+        }
+        #[cfg(feature = "metrics")]
+        assert_eq!(Runtime::metrics().utilization().len(), 2);
+    }
+
+    #[test]
+    fn spawn_batch_queues_each_task_at_its_own_priority() {
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let orders = [
+            FutureType::High,
+            FutureType::Low,
+            FutureType::High,
+            FutureType::Low,
+            FutureType::High,
+        ];
+        let tasks = spawn_batch(
+            orders
+                .iter()
+                .enumerate()
+                .map(|(i, &order)| (async move { i }, order))
+                .collect(),
+        );
+        assert_eq!(queued(FutureType::High), 3);
+        assert_eq!(queued(FutureType::Low), 2);
+        #[cfg(feature = "task-registry")]
+        {
+            let priorities: Vec<_> = tasks
+                .iter()
+                .map(|task| task.metadata().info().priority)
+                .collect();
+            assert_eq!(priorities, orders);
+        }
+
+        Runtime::graceful_restart(Runtime::new());
+        let outputs = futures_lite::future::block_on(join_all(tasks));
+        assert_eq!(outputs, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn demote_guard_reroutes_new_spawns_until_dropped() {
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let before = spawn_task(async { 0 }, FutureType::High);
+        let guard = Runtime::demote_new_spawns(FutureType::Low);
+        let demoted = spawn_task(async { 1 }, FutureType::High);
+        let low = spawn_task(async { 2 }, FutureType::Low);
+        assert_eq!(queued(FutureType::High), 1);
+        assert_eq!(queued(FutureType::Low), 2);
+        drop(guard);
+        let after = spawn_task(async { 3 }, FutureType::High);
+        assert_eq!(queued(FutureType::High), 2);
+
+        Runtime::graceful_restart(Runtime::new());
+        let outputs = futures_lite::future::block_on(join_all([before, demoted, low, after]));
+        assert_eq!(outputs, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn deterministic_pair_runs_each_priority_in_spawn_order() {
+        let _runtime = runtime(Runtime::deterministic_pair());
+        let tasks: Vec<_> = (0..40)
+            .map(|i| {
+                let order = if i % 3 == 0 {
+                    FutureType::Low
+                } else {
+                    FutureType::High
+                };
+                let task = spawn_task(
+                    async move { thread::sleep(Duration::from_micros(100)) },
+                    order,
+                );
+                (task.metadata().id(), order, task)
+            })
+            .collect();
+        let spawned: Vec<_> = tasks.iter().map(|&(id, order, _)| (id, order)).collect();
+        futures_lite::future::block_on(join_all(tasks.into_iter().map(|(_, _, task)| task)));
+        let executed = Runtime::take_execution_order();
+        assert_eq!(executed.len(), 40);
+        for priority in [FutureType::High, FutureType::Low] {
+            let queued: Vec<_> = spawned
+                .iter()
+                .filter(|&&(_, order)| order == priority)
+                .map(|&(id, _)| id)
+                .collect();
+            let ran: Vec<_> = executed
+                .iter()
+                .copied()
+                .filter(|id| queued.contains(id))
+                .collect();
+            assert_eq!(ran, queued, "{priority:?}");
+        }
+    }
+
+    #[test]
+    fn is_idle_only_with_nothing_queued_or_running() {
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        assert!(Runtime::is_idle());
+        let (release, released) = flume::unbounded::<()>();
+        let blocking = spawn_task(async move { released.recv().unwrap() }, FutureType::Low);
+        // queued, no worker to take it
+        assert!(!Runtime::is_idle());
+        Runtime::graceful_restart(Runtime::new());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while workers::in_flight() == 0 {
+            assert!(Instant::now() < deadline, "the task never started");
+            thread::sleep(Duration::from_millis(1));
+        }
+        // running
+        assert!(!Runtime::is_idle());
+        release.send(()).unwrap();
+        futures_lite::future::block_on(blocking);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !Runtime::is_idle() {
+            assert!(Instant::now() < deadline, "never went idle");
+            thread::sleep(Duration::from_millis(1));
+        }
+        // a task waiting on a timer doesn't count
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let sleeping = spawn_task(timer::sleep(Duration::from_secs(2)), FutureType::High);
+        while !Runtime::is_idle() {
+            assert!(Instant::now() < deadline, "a sleeping task kept it busy");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!sleeping.is_finished());
+        drop(sleeping);
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{FutureType, TaskRunnable};
+
+// Where in its queue a task lands when it is scheduled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpawnOrder {
+    // behind everything already queued, breadth-first
+    #[default]
+    Fifo,
+    // ahead of everything already queued, so the newest task runs next: depth-first, e.g. for
+    // recursive work where finishing a subtree before starting the next keeps its data warm
+    Lifo,
+}
+
+// Lifo tasks don't go through the pool's channel but onto a stack next to it, which workers
+// check first. `len` mirrors the stack's length so an empty stack costs no lock.
+struct Stack {
+    tasks: Mutex<Vec<TaskRunnable>>,
+    len: AtomicUsize,
+}
+
+static HIGH: Stack = Stack::new();
+static LOW: Stack = Stack::new();
+
+impl Stack {
+    const fn new() -> Self {
+        Self {
+            tasks: Mutex::new(Vec::new()),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+fn stack(queue: FutureType) -> &'static Stack {
+    match queue {
+        FutureType::High => &HIGH,
+        FutureType::Low => &LOW,
+    }
+}
+
+pub(crate) fn push(queue: FutureType, runnable: TaskRunnable) {
+    let stack = stack(queue);
+    let mut tasks = stack.tasks.lock().unwrap();
+    tasks.push(runnable);
+    stack.len.store(tasks.len(), Ordering::Release);
+}
+
+pub(crate) fn pop(queue: FutureType) -> Option<TaskRunnable> {
+    let stack = stack(queue);
+    if stack.len.load(Ordering::Acquire) == 0 {
+        return None;
+    }
+    let mut tasks = stack.tasks.lock().unwrap();
+    let runnable = tasks.pop();
+    stack.len.store(tasks.len(), Ordering::Release);
+    runnable
+}
+
+pub(crate) fn len(queue: FutureType) -> usize {
+    stack(queue).len.load(Ordering::Acquire)
+}
+
+// Everything on the stack, in the order the workers would have taken it
+pub(crate) fn drain(queue: FutureType) -> Vec<TaskRunnable> {
+    let stack = stack(queue);
+    let mut tasks = stack.tasks.lock().unwrap();
+    stack.len.store(0, Ordering::Release);
+    tasks.drain(..).rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures_lite::FutureExt;
+    use futures_lite::future::Boxed;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{Runtime, spawn_task_ordered};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    // A node of a binary tree two levels deep: logs its name, then spawns its children with
+    // `spawn_order` and leaves them to run on their own
+    fn node(name: String, spawn_order: SpawnOrder, log: Log) -> Boxed<()> {
+        async move {
+            log.lock().unwrap().push(name.clone());
+            if name.len() < 3 {
+                for child in ["a", "b"] {
+                    let child = node(format!("{name}{child}"), spawn_order, log.clone());
+                    spawn_task_ordered(child, FutureType::High, spawn_order).detach();
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn run_order(spawn_order: SpawnOrder) -> Vec<String> {
+        // one worker, so tasks run one at a time in the order they are taken off the queue
+        let _runtime = runtime(Runtime::new().with_high_num(1).with_low_num(0));
+        let log = Log::default();
+        spawn_task_ordered(
+            node("r".to_string(), spawn_order, log.clone()),
+            FutureType::High,
+            spawn_order,
+        )
+        .detach();
+        Runtime::wait_idle();
+        log.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn fifo_runs_the_tree_breadth_first_and_lifo_depth_first() {
+        assert_eq!(
+            run_order(SpawnOrder::Fifo),
+            ["r", "ra", "rb", "raa", "rab", "rba", "rbb"]
+        );
+        assert_eq!(
+            run_order(SpawnOrder::Lifo),
+            ["r", "rb", "rbb", "rba", "ra", "rab", "raa"]
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// Key-value pairs for correlating logs, e.g. a request id or a span name. Every task has its
+// own, starting out as a copy of whatever its spawner had at the time of the spawn, so a child
+// task logs with its parent's request id without passing it along by hand. Copies are shared
+// until one side sets something.
+pub(crate) type LogContext = Option<Arc<BTreeMap<String, String>>>;
+
+thread_local! {
+    // the context of the task being polled on this thread, or the thread's own outside tasks
+    static CURRENT: RefCell<LogContext> = const { RefCell::new(None) };
+}
+
+// Set `key` in the current task's log context, or the current thread's when called outside
+// any task. Tasks spawned afterwards inherit it; ones already spawned don't see it.
+pub fn set_log_context(key: impl Into<String>, value: impl Into<String>) {
+    CURRENT.with_borrow_mut(|context| {
+        let context = context.get_or_insert_with(Default::default);
+        Arc::make_mut(context).insert(key.into(), value.into());
+    })
+}
+
+// The value of `key` in the current task's log context
+pub fn log_context(key: &str) -> Option<String> {
+    CURRENT.with_borrow(|context| context.as_ref()?.get(key).cloned())
+}
+
+// What a task spawned right here starts out with
+pub(crate) fn capture() -> LogContext {
+    CURRENT.with_borrow(Clone::clone)
+}
+
+// Installs a task's context for the duration of a poll, and takes back what the poll left
+// there when dropped
+pub(crate) struct Entered<'a> {
+    task: &'a mut LogContext,
+}
+
+pub(crate) fn enter(task: &mut LogContext) -> Entered<'_> {
+    CURRENT.with_borrow_mut(|current| std::mem::swap(current, task));
+    Entered { task }
+}
+
+impl Drop for Entered<'_> {
+    fn drop(&mut self) {
+        CURRENT.with_borrow_mut(|current| std::mem::swap(current, self.task));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{FutureType, Runtime, spawn_task};
+
+    #[test]
+    fn child_task_inherits_its_parents_context() {
+        let _runtime = runtime(Runtime::new());
+        let parent = spawn_task(
+            async {
+                set_log_context("request_id", "42");
+                let child = spawn_task(
+                    async {
+                        let inherited = log_context("request_id");
+                        // the child's own changes stay with the child
+                        set_log_context("span", "child");
+                        (inherited, log_context("step"))
+                    },
+                    FutureType::Low,
+                );
+                // set after the spawn, so the child doesn't see it
+                set_log_context("step", "2");
+                let seen_by_child = child.await;
+                (seen_by_child, log_context("span"), log_context("step"))
+            },
+            FutureType::High,
+        );
+        let (seen_by_child, span, step) = futures_lite::future::block_on(parent);
+        assert_eq!(seen_by_child, (Some("42".to_string()), None));
+        assert_eq!(span, None);
+        assert_eq!(step.as_deref(), Some("2"));
+
+        // nothing leaked into the workers or this thread
+        let unrelated = spawn_task(async { log_context("request_id") }, FutureType::High);
+        assert_eq!(futures_lite::future::block_on(unrelated), None);
+        assert_eq!(log_context("request_id"), None);
+    }
+}
@@ -0,0 +1,475 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{FutureType, queued, workers};
+
+// Busy time is kept as nanoseconds since this instant
+static EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+fn now() -> u64 {
+    EPOCH.elapsed().as_nanos() as u64
+}
+
+// Time one worker thread has spent polling tasks. `busy_since` is the start of the poll in
+// progress plus one (0 while idle), so a long poll shows up before it returns.
+#[derive(Default)]
+struct WorkerClock {
+    busy: AtomicU64,
+    busy_since: AtomicU64,
+}
+
+impl WorkerClock {
+    fn busy_at(&self, now: u64) -> u64 {
+        let since = self.busy_since.load(Ordering::Acquire);
+        let running = if since == 0 {
+            0
+        } else {
+            now.saturating_sub(since - 1)
+        };
+        self.busy.load(Ordering::Acquire) + running
+    }
+}
+
+// Where the current sampling window of a worker started
+struct Slot {
+    clock: Arc<WorkerClock>,
+    sampled_at: u64,
+    sampled_busy: u64,
+}
+
+// Indexed by worker number, like the scheduler log
+static SLOTS: Mutex<Vec<Option<Slot>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static CLOCK: RefCell<Option<Arc<WorkerClock>>> = const { RefCell::new(None) };
+}
+
+// Called by each worker thread before it starts taking tasks
+pub(crate) fn register(worker: usize) {
+    let clock = Arc::new(WorkerClock::default());
+    let mut slots = SLOTS.lock().unwrap();
+    if slots.len() <= worker {
+        slots.resize_with(worker + 1, || None);
+    }
+    slots[worker] = Some(Slot {
+        clock: clock.clone(),
+        sampled_at: now(),
+        sampled_busy: 0,
+    });
+    CLOCK.set(Some(clock));
+}
+
+pub(crate) fn unregister(worker: usize) {
+    if let Some(slot) = SLOTS.lock().unwrap().get_mut(worker) {
+        *slot = None;
+    }
+}
+
+// Counts the worker as busy until dropped, also when the task panics
+pub(crate) struct BusySpan {
+    clock: Arc<WorkerClock>,
+    started: u64,
+}
+
+pub(crate) fn busy() -> Option<BusySpan> {
+    let clock = CLOCK.with_borrow(|clock| clock.clone())?;
+    let started = now();
+    clock.busy_since.store(started + 1, Ordering::Release);
+    Some(BusySpan { clock, started })
+}
+
+impl Drop for BusySpan {
+    fn drop(&mut self) {
+        // a sample taken between these two stores sees the poll slightly short, never twice
+        self.clock.busy_since.store(0, Ordering::Release);
+        self.clock
+            .busy
+            .fetch_add(now().saturating_sub(self.started), Ordering::AcqRel);
+    }
+}
+
+// How long each worker that is polling a task right now has been at it
+pub(crate) fn current_polls() -> Vec<(usize, Duration)> {
+    let now = now();
+    SLOTS
+        .lock()
+        .unwrap()
+        .iter()
+        .enumerate()
+        .filter_map(|(worker, slot)| {
+            let since = slot.as_ref()?.clock.busy_since.load(Ordering::Acquire);
+            (since != 0).then(|| (worker, Duration::from_nanos(now.saturating_sub(since - 1))))
+        })
+        .collect()
+}
+
+// Fraction of the current window each worker spent polling, closing the window and starting
+// the next one with `roll`. Workers that have exited are left out rather than reported as idle.
+fn sample_utilization(roll: bool) -> Vec<f64> {
+    let mut slots = SLOTS.lock().unwrap();
+    let now = now();
+    slots
+        .iter_mut()
+        .flatten()
+        .map(|slot| {
+            let busy = slot.clock.busy_at(now);
+            let window = now.saturating_sub(slot.sampled_at);
+            let used = busy.saturating_sub(slot.sampled_busy);
+            if roll {
+                slot.sampled_at = now;
+                slot.sampled_busy = busy;
+            }
+            if window == 0 {
+                0.0
+            } else {
+                (used as f64 / window as f64).min(1.0)
+            }
+        })
+        .collect()
+}
+
+// Event counts since the last Runtime::take_metrics
+static SPAWNED: AtomicU64 = AtomicU64::new(0);
+static POLLS: AtomicU64 = AtomicU64::new(0);
+static COMPLETED: AtomicU64 = AtomicU64::new(0);
+
+// Longest each queue has been, as seen right after each enqueue
+static HIGH_MAX_QUEUED: AtomicU64 = AtomicU64::new(0);
+static LOW_MAX_QUEUED: AtomicU64 = AtomicU64::new(0);
+
+// Called after every enqueue onto `queue`
+pub(crate) fn enqueued(queue: FutureType) {
+    let max = match queue {
+        FutureType::High => &HIGH_MAX_QUEUED,
+        FutureType::Low => &LOW_MAX_QUEUED,
+    };
+    max.fetch_max(queued(queue) as u64, Ordering::Relaxed);
+}
+
+pub(crate) fn spawned() {
+    SPAWNED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn polled(completed: bool) {
+    POLLS.fetch_add(1, Ordering::Relaxed);
+    if completed {
+        COMPLETED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Counts for the tasks carrying one label. Only labels some task was spawned with get an
+// entry, and unlabelled tasks never touch any of this.
+#[derive(Debug, Default)]
+pub(crate) struct LabelCounters {
+    spawned: AtomicU64,
+    polls: AtomicU64,
+    completed: AtomicU64,
+    poll_nanos: AtomicU64,
+}
+
+static LABELS: Mutex<BTreeMap<(String, String), Arc<LabelCounters>>> = Mutex::new(BTreeMap::new());
+
+// Counts the spawn of a task with `labels` and hands back the counters its record keeps, so
+// its polls are counted without going through the map again
+pub(crate) fn label_counters(labels: &[(String, String)]) -> Box<[Arc<LabelCounters>]> {
+    if labels.is_empty() {
+        return Box::default();
+    }
+    let mut counters = LABELS.lock().unwrap();
+    labels
+        .iter()
+        .map(|label| {
+            let counters = counters.entry(label.clone()).or_default().clone();
+            counters.spawned.fetch_add(1, Ordering::Relaxed);
+            counters
+        })
+        .collect()
+}
+
+pub(crate) fn label_polled(counters: &[Arc<LabelCounters>], took: Duration, completed: bool) {
+    for counters in counters {
+        counters.polls.fetch_add(1, Ordering::Relaxed);
+        counters
+            .poll_nanos
+            .fetch_add(took.as_nanos() as u64, Ordering::Relaxed);
+        if completed {
+            counters.completed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// What the tasks carrying one label did, see Metrics::label
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LabelMetrics {
+    spawned: u64,
+    polls: u64,
+    completed: u64,
+    poll_time: Duration,
+}
+
+impl LabelMetrics {
+    pub fn spawned(&self) -> u64 {
+        self.spawned
+    }
+
+    pub fn polls(&self) -> u64 {
+        self.polls
+    }
+
+    pub fn completed(&self) -> u64 {
+        self.completed
+    }
+
+    // Time spent polling them, e.g. to compare tenants against their budgets
+    pub fn poll_time(&self) -> Duration {
+        self.poll_time
+    }
+}
+
+// Snapshot handed out by Runtime::metrics
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    utilization: Vec<f64>,
+    high_queued: usize,
+    low_queued: usize,
+    high_max_queued: u64,
+    low_max_queued: u64,
+    in_flight: usize,
+    spawned: u64,
+    polls: u64,
+    completed: u64,
+    labels: BTreeMap<(String, String), LabelMetrics>,
+}
+
+impl Metrics {
+    // Per running worker, in start order (high pool first), the fraction of the sampling
+    // window spent running tasks, from 0.0 (idle) to 1.0 (never idle). The window runs from the
+    // previous Runtime::take_metrics, or the worker's start, to this snapshot. Workers retired
+    // by a graceful restart or lost to a panic aren't listed.
+    pub fn utilization(&self) -> Vec<f64> {
+        self.utilization.clone()
+    }
+
+    // Tasks sitting in the given queue, spawned or woken but not yet picked up by a worker
+    pub fn queued(&self, queue: FutureType) -> usize {
+        match queue {
+            FutureType::High => self.high_queued,
+            FutureType::Low => self.low_queued,
+        }
+    }
+
+    // The most tasks the given queue has held at once, since counting last started over like
+    // `spawned`. Unlike `queued` it doesn't miss a burst that was drained between two samples.
+    pub fn max_queued(&self, queue: FutureType) -> u64 {
+        match queue {
+            FutureType::High => self.high_max_queued,
+            FutureType::Low => self.low_max_queued,
+        }
+    }
+
+    // Tasks being polled by a worker right now, across both pools. Next to `queued` this tells
+    // a backlog (queued grows while in_flight sits at the worker count) from idle capacity.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    // Tasks spawned, polls run and tasks completed since counting last started over, see
+    // Runtime::take_metrics
+    pub fn spawned(&self) -> u64 {
+        self.spawned
+    }
+
+    pub fn polls(&self) -> u64 {
+        self.polls
+    }
+
+    pub fn completed(&self) -> u64 {
+        self.completed
+    }
+
+    // The same counts plus poll time, for just the tasks spawned with the label `key=value`
+    // (see spawn_with_labels). All zero if no such task was counted.
+    pub fn label(&self, key: &str, value: &str) -> LabelMetrics {
+        self.labels
+            .iter()
+            .find(|((k, v), _)| k == key && v == value)
+            .map_or_else(LabelMetrics::default, |(_, metrics)| *metrics)
+    }
+
+    // Every label counted, ordered by key and then value
+    pub fn labels(&self) -> impl Iterator<Item = (&str, &str, &LabelMetrics)> {
+        self.labels
+            .iter()
+            .map(|((key, value), metrics)| (key.as_str(), value.as_str(), metrics))
+    }
+}
+
+pub(crate) fn snapshot() -> Metrics {
+    let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    sample(count, false, false)
+}
+
+// Each counter is swapped out rather than read and then zeroed, so every event lands in
+// exactly one snapshot, whichever side of the reset it happens on
+pub(crate) fn take() -> Metrics {
+    let count = |counter: &AtomicU64| counter.swap(0, Ordering::Relaxed);
+    sample(count, true, true)
+}
+
+// With `prune`, labels no live task carries are dropped once read. Their counters can't move
+// anymore since new tasks only get them through the lock held here, so nothing is lost.
+fn sample_labels(
+    count: &impl Fn(&AtomicU64) -> u64,
+    prune: bool,
+) -> BTreeMap<(String, String), LabelMetrics> {
+    let mut sampled = BTreeMap::new();
+    LABELS.lock().unwrap().retain(|label, counters| {
+        let unused = Arc::strong_count(counters) == 1;
+        let metrics = LabelMetrics {
+            spawned: count(&counters.spawned),
+            polls: count(&counters.polls),
+            completed: count(&counters.completed),
+            poll_time: Duration::from_nanos(count(&counters.poll_nanos)),
+        };
+        sampled.insert(label.clone(), metrics);
+        !(prune && unused)
+    });
+    sampled
+}
+
+fn sample(count: impl Fn(&AtomicU64) -> u64, prune: bool, roll: bool) -> Metrics {
+    Metrics {
+        utilization: sample_utilization(roll),
+        high_queued: queued(FutureType::High),
+        low_queued: queued(FutureType::Low),
+        high_max_queued: count(&HIGH_MAX_QUEUED),
+        low_max_queued: count(&LOW_MAX_QUEUED),
+        in_flight: workers::in_flight(),
+        spawned: count(&SPAWNED),
+        polls: count(&POLLS),
+        completed: count(&COMPLETED),
+        labels: sample_labels(&count, prune),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{Runtime, join_all, spawn_task};
+
+    #[test]
+    fn busy_worker_shows_higher_utilization_than_idle_one() {
+        let _runtime = runtime(
+            Runtime::new()
+                .with_high_num(1)
+                .with_low_num(1)
+                .with_stealing(false),
+        );
+        // starts a fresh window
+        Runtime::take_metrics();
+        let busy = spawn_task(
+            async { thread::sleep(Duration::from_millis(200)) },
+            FutureType::High,
+        );
+        futures_lite::future::block_on(busy);
+        // the worker may still be wrapping up the poll that woke us
+        while workers::in_flight() != 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let utilization = Runtime::metrics().utilization();
+        // high worker first, then the low one, which had nothing to run
+        let [high, low] = utilization[..] else {
+            panic!("expected two workers, got {utilization:?}");
+        };
+        assert!(high > 0.5, "busy worker at {high}");
+        assert!(low < 0.1, "idle worker at {low}");
+        // reading didn't close the window, taking does
+        let again = Runtime::metrics().utilization()[0];
+        assert!(again > 0.5, "busy worker at {again} on a second read");
+        Runtime::take_metrics();
+        let fresh = Runtime::metrics().utilization()[0];
+        assert!(fresh < 0.1, "busy worker at {fresh} in a fresh window");
+    }
+
+    #[test]
+    fn in_flight_sits_at_the_worker_count_under_a_backlog() {
+        let _runtime = runtime(Runtime::new().with_high_num(2).with_low_num(1));
+        let release = Arc::new(AtomicBool::new(false));
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let release = release.clone();
+                let work = async move {
+                    while !release.load(Ordering::Relaxed) {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                };
+                spawn_task(work, FutureType::High)
+            })
+            .collect();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Runtime::metrics().in_flight() < 3 {
+            assert!(Instant::now() < deadline, "the workers never all got busy");
+            thread::sleep(Duration::from_millis(1));
+        }
+        let metrics = Runtime::metrics();
+        assert_eq!(metrics.in_flight(), 3);
+        assert_eq!(metrics.queued(FutureType::High), 5);
+        release.store(true, Ordering::Relaxed);
+        futures_lite::future::block_on(join_all(tasks));
+    }
+
+    #[test]
+    fn take_metrics_covers_only_what_happened_since_the_last_take() {
+        let _runtime = runtime(Runtime::new());
+        let workload = |tasks: u64| {
+            let tasks: Vec<_> = (0..tasks)
+                .map(|i| spawn_task(async move { i }, FutureType::Low))
+                .collect();
+            futures_lite::future::block_on(join_all(tasks));
+        };
+        workload(5);
+        let first = Runtime::take_metrics();
+        assert_eq!(
+            (first.spawned(), first.polls(), first.completed()),
+            (5, 5, 5)
+        );
+        workload(3);
+        // a snapshot reads without starting over
+        assert_eq!(Runtime::metrics().spawned(), 3);
+        let second = Runtime::take_metrics();
+        assert_eq!(
+            (second.spawned(), second.polls(), second.completed()),
+            (3, 3, 3)
+        );
+        let third = Runtime::take_metrics();
+        assert_eq!(
+            (third.spawned(), third.polls(), third.completed()),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn max_queued_remembers_a_drained_burst() {
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let burst: Vec<_> = (0..25)
+            .map(|i| spawn_task(async move { i }, FutureType::High))
+            .collect();
+        Runtime::graceful_restart(Runtime::new());
+        futures_lite::future::block_on(join_all(burst));
+        let metrics = Runtime::take_metrics();
+        // long gone from the queue, but not from the high-water mark
+        assert_eq!(metrics.queued(FutureType::High), 0);
+        assert_eq!(metrics.max_queued(FutureType::High), 25);
+        assert_eq!(metrics.max_queued(FutureType::Low), 0);
+        assert_eq!(Runtime::metrics().max_queued(FutureType::High), 0);
+    }
+}
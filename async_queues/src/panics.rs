@@ -0,0 +1,168 @@
+use std::panic::{AssertUnwindSafe, catch_unwind, resume_unwind};
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use std::thread;
+
+use crate::registry::Expired;
+
+// What happens when a task's future panics. The same policy applies on both pools and to
+// inline polls on the spawning thread, so a panic means the same thing whatever priority the
+// task was spawned with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    // The panic is caught where the task is polled and kept as the task's outcome. The worker
+    // carries on, and awaiting the Task re-raises the original panic: join! panics with it,
+    // try_join! hands it back as Err.
+    #[default]
+    CatchAndFail,
+    // The panic unwinds out of the task into whichever thread polled it, so a worker thread
+    // dies with it and its pool is one worker short from then on, which Runtime::worker_count
+    // and spawn_when_capacity take into account. The Task is left closed:
+    // awaiting it panics ("Task polled after completion"), which join!/try_join! observe the
+    // same way as above but without the original payload.
+    Propagate,
+    // Any task panic aborts the process once the panic message has been printed
+    Abort,
+}
+
+impl PanicPolicy {
+    fn from_u8(policy: u8) -> Self {
+        match policy {
+            1 => Self::Propagate,
+            2 => Self::Abort,
+            _ => Self::CatchAndFail,
+        }
+    }
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(PanicPolicy::CatchAndFail as u8);
+
+pub(crate) fn set_policy(policy: PanicPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+fn policy() -> PanicPolicy {
+    PanicPolicy::from_u8(POLICY.load(Ordering::Relaxed))
+}
+
+// Task panics since the last health check, whatever the policy did with them
+static PANICS: AtomicU64 = AtomicU64::new(0);
+
+// Held across every poll of a task; counts the poll as a panic if it unwinds
+pub(crate) struct PanicWatch;
+
+impl Drop for PanicWatch {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            PANICS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+pub(crate) fn take_panics() -> u64 {
+    PANICS.swap(0, Ordering::Relaxed)
+}
+
+// Whether new tasks keep a panic as their output for the Task handle to re-raise
+pub(crate) fn captured_by_task() -> bool {
+    policy() == PanicPolicy::CatchAndFail
+}
+
+// Every poll of a task, on a worker or inline, goes through here. Under CatchAndFail the task
+// already holds on to its own panic; catching here too covers tasks spawned before the policy
+// was switched over. A task aborted for outliving its TTL isn't a panic under any policy.
+pub(crate) fn run(poll: impl FnOnce()) {
+    let Err(payload) = catch_unwind(AssertUnwindSafe(poll)) else {
+        return;
+    };
+    if payload.is::<Expired>() {
+        return;
+    }
+    match policy() {
+        PanicPolicy::CatchAndFail => {}
+        PanicPolicy::Propagate => resume_unwind(payload),
+        PanicPolicy::Abort => std::process::abort(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::test_support::{run_child, runtime, scenario};
+    use crate::{FutureType, Runtime, spawn_task, try_join};
+
+    const PRIORITIES: [FutureType; 2] = [FutureType::High, FutureType::Low];
+
+    fn config(policy: PanicPolicy) -> Runtime {
+        Runtime::new()
+            .with_high_num(2)
+            .with_low_num(2)
+            .with_stealing(false)
+            .with_panic_policy(policy)
+    }
+
+    #[test]
+    fn catch_and_fail_hands_the_panic_to_the_task() {
+        let _runtime = runtime(config(PanicPolicy::CatchAndFail));
+        take_panics();
+        for priority in PRIORITIES {
+            let task = spawn_task(async { panic!("boom") }, priority);
+            let [outcome] = <[_; 1]>::try_from(try_join!(task)).unwrap();
+            let payload = outcome.unwrap_err();
+            assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+            // the worker that polled it carries on
+            assert_eq!(Runtime::worker_count(priority), 2);
+            let task = spawn_task(async { 7 }, priority);
+            assert_eq!(futures_lite::future::block_on(task), 7);
+        }
+        assert_eq!(take_panics(), 2);
+    }
+
+    #[test]
+    fn propagate_takes_the_worker_down() {
+        let _runtime = runtime(config(PanicPolicy::Propagate));
+        for priority in PRIORITIES {
+            let task = spawn_task(async { panic!("boom") }, priority);
+            let [outcome] = <[_; 1]>::try_from(try_join!(task)).unwrap();
+            assert!(outcome.is_err());
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Runtime::worker_count(priority) != 1 {
+                assert!(Instant::now() < deadline, "the worker outlived its panic");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            // the pool carries on with the worker it has left
+            let task = spawn_task(async { 7 }, priority);
+            assert_eq!(futures_lite::future::block_on(task), 7);
+        }
+        assert_eq!(Runtime::worker_count(FutureType::High), 1);
+        assert_eq!(Runtime::worker_count(FutureType::Low), 1);
+    }
+
+    #[test]
+    fn abort_ends_the_process() {
+        if let Some(scenario) = scenario() {
+            let _runtime = runtime(config(PanicPolicy::Abort));
+            let priority = match scenario.as_str() {
+                "abort-high" => FutureType::High,
+                _ => FutureType::Low,
+            };
+            spawn_task(async { panic!("boom") }, priority).detach();
+            // awaiting the task could see it closed by the unwinding before the abort
+            std::thread::sleep(Duration::from_secs(60));
+            unreachable!("the process outlived the panic");
+        }
+        for scenario in ["abort-high", "abort-low"] {
+            let output = run_child(
+                "panics::tests::abort_ends_the_process",
+                scenario,
+                Duration::from_secs(30),
+            )
+            .expect("the aborting process didn't exit");
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(!output.status.success(), "{stderr}");
+            assert!(stderr.contains("boom"), "{stderr}");
+            assert!(!stderr.contains("outlived"), "{stderr}");
+        }
+    }
+}
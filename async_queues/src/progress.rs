@@ -0,0 +1,86 @@
+use std::future::Future;
+use std::panic::Location;
+
+use flume::{Receiver, Sender};
+
+use crate::{FutureType, Task, coop};
+
+// Handed to the task body so it can report how far along it is.
+// Values are meant to be fractions in 0.0..=1.0 but nothing enforces that.
+#[derive(Clone)]
+pub struct ProgressSender {
+    sender: Sender<f64>,
+}
+
+impl ProgressSender {
+    // never blocks; updates are dropped if nobody is listening anymore
+    pub fn send(&self, progress: f64) {
+        let _ = self.sender.send(progress);
+    }
+}
+
+// Consumer side of the progress updates. Once the task is done and every update
+// has been read, `recv` resolves to None.
+pub struct ProgressReceiver {
+    receiver: Receiver<f64>,
+}
+
+impl ProgressReceiver {
+    pub fn try_recv(&self) -> Option<f64> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub async fn recv(&self) -> Option<f64> {
+        coop::consume_budget().await;
+        self.receiver.recv_async().await.ok()
+    }
+
+    // drains everything queued so far and returns the most recent value
+    pub fn latest(&self) -> Option<f64> {
+        self.receiver.try_iter().last()
+    }
+}
+
+// Spawn a task that can report progress while it runs, alongside its final value
+#[track_caller]
+pub fn spawn_with_progress<F, Fut, T>(factory: F, order: FutureType) -> (Task<T>, ProgressReceiver)
+where
+    F: FnOnce(ProgressSender) -> Fut,
+    Fut: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = flume::unbounded();
+    let future = factory(ProgressSender { sender });
+    let task = crate::spawn(future, order, None, Location::caller());
+    (task, ProgressReceiver { receiver })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Runtime;
+    use crate::test_support::runtime;
+
+    #[test]
+    fn receiver_observes_every_update_then_the_end() {
+        let _runtime = runtime(Runtime::new());
+        let (task, progress) = spawn_with_progress(
+            |sender| async move {
+                for step in 1..=4 {
+                    sender.send(step as f64 * 0.25);
+                }
+                "done"
+            },
+            FutureType::High,
+        );
+        let (updates, output) = futures_lite::future::block_on(async {
+            let mut updates = Vec::new();
+            while let Some(update) = progress.recv().await {
+                updates.push(update);
+            }
+            (updates, task.await)
+        });
+        assert_eq!(updates, [0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(output, "done");
+    }
+}
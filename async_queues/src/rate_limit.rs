@@ -0,0 +1,125 @@
+use std::future::Future;
+use std::panic::Location;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::timer::sleep;
+use crate::{FutureType, Task};
+
+// Token bucket pacing how fast tasks are handed to the runtime: it refills at `rate` spawns
+// per second and holds at most `burst` of them, so up to `burst` spawns go through at once
+// after a quiet spell and the rate holds from then on. Clones share the same bucket.
+#[derive(Clone)]
+pub struct SpawnLimiter {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+// Longest a spawn sleeps before checking the bucket again, so a very low rate can't ask for a
+// wait too long for a Duration or an Instant
+const MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+impl SpawnLimiter {
+    // Panics unless `rate` is a positive number: a limiter that never lets anything through
+    // would leave every spawn waiting forever. A `burst` of 0 is taken as 1.
+    pub fn new(rate: f64, burst: u32) -> Self {
+        assert!(
+            rate.is_finite() && rate > 0.0,
+            "SpawnLimiter rate must be positive and finite, got {rate}"
+        );
+        let burst = burst.max(1) as f64;
+        Self {
+            inner: Arc::new(Inner {
+                rate,
+                burst,
+                bucket: Mutex::new(Bucket {
+                    tokens: burst,
+                    refilled_at: Instant::now(),
+                }),
+            }),
+        }
+    }
+
+    // Takes a token if there is one, otherwise says how long until there will be
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let mut bucket = self.inner.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.inner.rate).min(self.inner.burst);
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        let wait = (1.0 - bucket.tokens) / self.inner.rate;
+        Err(Duration::try_from_secs_f64(wait).map_or(MAX_WAIT, |wait| wait.min(MAX_WAIT)))
+    }
+}
+
+// Spawn `future` once `limiter` has a token for it, so the tasks reach the queues no faster
+// than its rate, e.g. to keep a flood of requests from overwhelming a downstream system. What
+// the tasks do once running isn't limited. Resolves to the Task itself, awaiting that is up to
+// the caller.
+#[allow(clippy::async_yields_async)]
+#[track_caller]
+pub fn spawn_rate_limited<F, T>(
+    limiter: &SpawnLimiter,
+    future: F,
+    order: FutureType,
+) -> impl Future<Output = Task<T>>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let location = Location::caller();
+    let limiter = limiter.clone();
+    async move {
+        while let Err(wait) = limiter.try_acquire() {
+            sleep(wait).await;
+        }
+        crate::spawn(future, order, None, location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{Runtime, join_all};
+
+    #[test]
+    fn spawns_are_held_to_the_rate_after_the_burst() {
+        let _runtime = runtime(Runtime::new());
+        let limiter = SpawnLimiter::new(50.0, 5);
+        let started = Instant::now();
+        let tasks = futures_lite::future::block_on(async {
+            let mut tasks = Vec::new();
+            for i in 0..20 {
+                tasks.push(spawn_rate_limited(&limiter, async move { i }, FutureType::Low).await);
+            }
+            tasks
+        });
+        let took = started.elapsed();
+        // five right away, the other fifteen 20ms apart
+        assert!(took >= Duration::from_millis(280), "took {took:?}");
+        assert!(took < Duration::from_secs(2), "took {took:?}");
+        let outputs = futures_lite::future::block_on(join_all(tasks));
+        assert_eq!(outputs, (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic(expected = "rate must be positive")]
+    fn zero_rate_is_rejected() {
+        SpawnLimiter::new(0.0, 1);
+    }
+}
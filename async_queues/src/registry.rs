@@ -0,0 +1,721 @@
+use std::cell::Cell;
+#[cfg(feature = "task-registry")]
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+#[cfg(feature = "task-registry")]
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, LazyLock, Mutex};
+#[cfg(feature = "task-registry")]
+use std::task::Waker;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use pin_project_lite::pin_project;
+
+use crate::log_context::{self, LogContext};
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::timer::{Sleep, sleep_until};
+use crate::{FutureType, coop, detached, panics, watchdog};
+
+// With the task-registry feature, every live task is kept here from spawn until its future
+// completes or is dropped. A BTreeMap keeps the enumeration ordered by id, i.e. by spawn order.
+#[cfg(feature = "task-registry")]
+static TASKS: Mutex<BTreeMap<TaskId, Entry>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+// Live tasks are counted either way, for wait_idle. EMPTY is notified under EMPTY_LOCK
+// whenever the count drops to zero.
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static EMPTY_LOCK: Mutex<()> = Mutex::new(());
+static EMPTY: Condvar = Condvar::new();
+// Wakers of join_ids futures, by the task they are waiting for
+#[cfg(feature = "task-registry")]
+static WAITERS: Mutex<Option<HashMap<TaskId, Vec<Waker>>>> = Mutex::new(None);
+
+// Tasks spawned from outside any task are at depth 0, a task spawned while another one is
+// being polled one deeper than that one. Spawns past the limit set through
+// Runtime::with_max_spawn_depth run on the spawning thread instead of being queued.
+static MAX_DEPTH: AtomicU32 = AtomicU32::new(u32::MAX);
+
+thread_local! {
+    // depth of the task being polled on this thread, if any
+    static CURRENT_DEPTH: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+// Lifetime given to every task not spawned with a TTL of its own, set through
+// Runtime::with_task_ttl. Zero means none.
+static DEFAULT_TTL: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn set_default_ttl(ttl: Option<Duration>) {
+    let nanos = ttl.map_or(0, |ttl| ttl.as_nanos().clamp(1, u64::MAX as u128) as u64);
+    DEFAULT_TTL.store(nanos, Ordering::Relaxed);
+}
+
+fn default_ttl() -> Option<Duration> {
+    match DEFAULT_TTL.load(Ordering::Relaxed) {
+        0 => None,
+        nanos => Some(Duration::from_nanos(nanos)),
+    }
+}
+
+// Payload a task unwinds with when its TTL runs out. It goes through resume_unwind, so no
+// panic message is printed, and panics::run lets it pass under every policy.
+pub(crate) struct Expired;
+
+pub(crate) fn set_max_depth(depth: Option<u32>) {
+    MAX_DEPTH.store(depth.unwrap_or(u32::MAX), Ordering::Relaxed);
+}
+
+fn spawn_depth() -> u32 {
+    CURRENT_DEPTH
+        .get()
+        .map_or(0, |depth| depth.saturating_add(1))
+}
+
+// Whether a task spawned right here would be deeper than allowed
+pub(crate) fn too_deep() -> bool {
+    spawn_depth() > MAX_DEPTH.load(Ordering::Relaxed)
+}
+
+// Restores the outer depth once a poll is over, also when it panics
+struct Polling(Option<u32>);
+
+impl Drop for Polling {
+    fn drop(&mut self) {
+        CURRENT_DEPTH.set(self.0);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TaskId(u64);
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task-{}", self.0)
+    }
+}
+
+#[cfg(feature = "task-registry")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskState {
+    // sitting in a queue waiting for a worker
+    Queued,
+    // being polled by a worker right now
+    Running,
+    // returned Pending and is waiting to be woken
+    Idle,
+    // cancelled while queued, dropped once a worker dequeues it
+    Cancelled,
+}
+
+// Point-in-time copy of a task's record, handed out by `Runtime::live_tasks`
+#[cfg(feature = "task-registry")]
+#[derive(Clone, Debug)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: Option<String>,
+    pub labels: Vec<(String, String)>,
+    pub priority: FutureType,
+    pub location: &'static Location<'static>,
+    pub spawned_at: Instant,
+    // when the task gets aborted if it is still around, see Runtime::with_task_ttl
+    pub deadline: Option<Instant>,
+    pub depth: u32,
+    pub polls: u64,
+    // when its last poll returned, None if it hasn't been polled to the end yet
+    pub last_polled: Option<Instant>,
+    pub state: TaskState,
+}
+
+#[cfg(feature = "task-registry")]
+impl TaskInfo {
+    // The value of the label `key` the task was spawned with, if any
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// Points at the record inside a live task. Registration::drop takes the entry out before the
+// task's future is gone, and the record is only dropped with the task after that.
+#[cfg(feature = "task-registry")]
+struct Entry(NonNull<TaskRecord>);
+
+// SAFETY: TaskRecord is Sync, and the entry doesn't outlive the record, see above
+#[cfg(feature = "task-registry")]
+unsafe impl Send for Entry {}
+
+#[cfg(feature = "task-registry")]
+impl Entry {
+    fn record(&self) -> &TaskRecord {
+        // SAFETY: only read under the TASKS lock, while the entry is still in the map
+        unsafe { self.0.as_ref() }
+    }
+}
+
+// When a task spawned with a TTL is due, and whether it got aborted for running past it.
+// Shared so TaskExt::join can still check it once the Task is behind fallible().
+#[derive(Debug)]
+pub(crate) struct Deadline {
+    at: Instant,
+    passed: AtomicBool,
+}
+
+impl Deadline {
+    pub(crate) fn passed(&self) -> bool {
+        self.passed.load(Ordering::Acquire)
+    }
+}
+
+// The record behind a task. It is the task's metadata, so it lives in the task's allocation
+// rather than one of its own, and a `Task` handle can tell which registry entry it belongs to.
+// What only the registry or the scheduler log read is kept with those features alone: the
+// record is part of every task's allocation, and a bigger one makes every spawn slower.
+#[derive(Debug)]
+pub struct TaskRecord {
+    id: TaskId,
+    #[cfg(feature = "task-registry")]
+    name: Option<String>,
+    // None rather than an empty slice, which would still be an allocation per task
+    #[cfg(any(feature = "scheduler-log", feature = "task-registry"))]
+    labels: Option<Arc<[(String, String)]>>,
+    // per-label metrics, one entry per label
+    #[cfg(feature = "metrics")]
+    label_counters: Box<[Arc<metrics::LabelCounters>]>,
+    #[cfg(feature = "task-registry")]
+    priority: FutureType,
+    location: &'static Location<'static>,
+    #[cfg(feature = "task-registry")]
+    spawned_at: Instant,
+    // only tasks with a TTL have one
+    deadline: Option<Arc<Deadline>>,
+    depth: u32,
+    #[cfg(feature = "task-registry")]
+    polls: AtomicU64,
+    // nanoseconds from spawned_at to the end of the last poll, plus one; 0 until then
+    #[cfg(feature = "task-registry")]
+    last_polled: AtomicU64,
+    #[cfg(feature = "task-registry")]
+    state: AtomicU8,
+    // 0 while someone holds the Task, 1 once handed to `detach`, 2 once the future is gone
+    detached: AtomicU8,
+}
+
+impl TaskRecord {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    #[cfg(feature = "task-registry")]
+    pub fn info(&self) -> TaskInfo {
+        TaskInfo {
+            id: self.id,
+            name: self.name.clone(),
+            labels: self.labels.as_deref().unwrap_or_default().to_vec(),
+            priority: self.priority,
+            location: self.location,
+            spawned_at: self.spawned_at,
+            deadline: self.deadline.as_ref().map(|deadline| deadline.at),
+            depth: self.depth,
+            polls: self.polls.load(Ordering::Relaxed),
+            last_polled: self.last_polled(),
+            state: self.state(),
+        }
+    }
+
+    // Shared rather than copied, for the scheduler log's events
+    pub(crate) fn labels(&self) -> Arc<[(String, String)]> {
+        static NONE: LazyLock<Arc<[(String, String)]>> = LazyLock::new(|| Arc::new([]));
+        #[cfg(any(feature = "scheduler-log", feature = "task-registry"))]
+        if let Some(labels) = &self.labels {
+            return labels.clone();
+        }
+        NONE.clone()
+    }
+
+    #[cfg(feature = "task-registry")]
+    fn last_polled(&self) -> Option<Instant> {
+        match self.last_polled.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(self.spawned_at + Duration::from_nanos(nanos - 1)),
+        }
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Arc<Deadline>> {
+        self.deadline.clone()
+    }
+
+    // false if the task already finished
+    pub(crate) fn mark_detached(&self) -> bool {
+        self.detached
+            .compare_exchange(0, 1, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    #[cfg(feature = "task-registry")]
+    pub(crate) fn set_state(&self, state: TaskState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
+    #[cfg(feature = "task-registry")]
+    fn state(&self) -> TaskState {
+        match self.state.load(Ordering::Acquire) {
+            0 => TaskState::Queued,
+            1 => TaskState::Running,
+            2 => TaskState::Idle,
+            _ => TaskState::Cancelled,
+        }
+    }
+
+    // Moves a queued task to `to`. The worker claims a dequeued task this way before polling
+    // it, so it can't race with a cancellation: whichever gets there first wins.
+    #[cfg(feature = "task-registry")]
+    fn leave_queue(&self, to: TaskState) -> bool {
+        self.state
+            .compare_exchange(
+                TaskState::Queued as u8,
+                to as u8,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+    }
+
+    // false if the task was cancelled while it sat in the queue, which only
+    // Runtime::cancel_spawned_before does
+    pub(crate) fn claim(&self) -> bool {
+        #[cfg(feature = "task-registry")]
+        return self.leave_queue(TaskState::Running);
+        #[cfg(not(feature = "task-registry"))]
+        true
+    }
+}
+
+// Registers a task built around `record`, which must be its metadata, as live. Called right
+// after building it, before it can be polled or dropped: Registration::drop undoes this.
+pub(crate) fn register(record: &TaskRecord) {
+    register_batch([record]);
+}
+
+// Same for a whole batch of tasks, under one lock
+pub(crate) fn register_batch<'a>(records: impl IntoIterator<Item = &'a TaskRecord>) {
+    #[cfg(feature = "task-registry")]
+    let mut tasks = TASKS.lock().unwrap();
+    for _record in records {
+        LIVE.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "task-registry")]
+        tasks.insert(_record.id, Entry(NonNull::from(_record)));
+        #[cfg(feature = "metrics")]
+        metrics::spawned();
+    }
+}
+
+pub(crate) fn new_record(
+    name: Option<String>,
+    labels: Vec<(String, String)>,
+    ttl: Option<Duration>,
+    priority: FutureType,
+    location: &'static Location<'static>,
+) -> TaskRecord {
+    let ttl = ttl.or_else(default_ttl);
+    // the clock is only read if something is going to look at the time
+    let spawned_at = (cfg!(feature = "task-registry") || ttl.is_some()).then(Instant::now);
+    #[cfg(feature = "metrics")]
+    let label_counters = metrics::label_counters(&labels);
+    #[cfg(not(feature = "task-registry"))]
+    let _ = (name, priority);
+    #[cfg(not(any(feature = "scheduler-log", feature = "task-registry")))]
+    let _ = labels;
+    TaskRecord {
+        id: TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+        #[cfg(feature = "task-registry")]
+        name,
+        #[cfg(any(feature = "scheduler-log", feature = "task-registry"))]
+        labels: (!labels.is_empty()).then(|| labels.into()),
+        #[cfg(feature = "metrics")]
+        label_counters,
+        #[cfg(feature = "task-registry")]
+        priority,
+        location,
+        #[cfg(feature = "task-registry")]
+        spawned_at: spawned_at.unwrap(),
+        deadline: ttl.zip(spawned_at).map(|(ttl, spawned_at)| {
+            Arc::new(Deadline {
+                at: spawned_at + ttl,
+                passed: AtomicBool::new(false),
+            })
+        }),
+        depth: spawn_depth(),
+        #[cfg(feature = "task-registry")]
+        polls: AtomicU64::new(0),
+        #[cfg(feature = "task-registry")]
+        last_polled: AtomicU64::new(0),
+        #[cfg(feature = "task-registry")]
+        state: AtomicU8::new(TaskState::Queued as u8),
+        detached: AtomicU8::new(0),
+    }
+}
+
+#[cfg(feature = "task-registry")]
+pub(crate) fn live_tasks() -> Vec<TaskInfo> {
+    TASKS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|entry| entry.record().info())
+        .collect()
+}
+
+// Blocks until there are no live tasks left, queued, running or waiting to be woken
+pub(crate) fn wait_idle() {
+    let mut lock = EMPTY_LOCK.lock().unwrap();
+    while LIVE.load(Ordering::Acquire) != 0 {
+        lock = EMPTY.wait(lock).unwrap();
+    }
+}
+
+// Resolves once none of `ids` is a live task anymore. Ids of tasks that already finished, or
+// never existed, count as done.
+#[cfg(feature = "task-registry")]
+pub(crate) fn join_ids(mut ids: Vec<TaskId>) -> impl Future<Output = ()> {
+    std::future::poll_fn(move |cx| {
+        let tasks = TASKS.lock().unwrap();
+        ids.retain(|id| tasks.contains_key(id));
+        let Some(&id) = ids.first() else {
+            return Poll::Ready(());
+        };
+        // registered under the TASKS lock, so the task can't finish before the waker is in
+        let mut waiters = WAITERS.lock().unwrap();
+        let wakers = waiters
+            .get_or_insert_with(HashMap::new)
+            .entry(id)
+            .or_default();
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    })
+}
+
+// Marks every task spawned before `cutoff` that is still waiting in a queue as cancelled and
+// returns how many there were. Tasks that are running or waiting to be woken are left alone.
+#[cfg(feature = "task-registry")]
+pub(crate) fn cancel_queued_before(cutoff: Instant) -> usize {
+    TASKS
+        .lock()
+        .unwrap()
+        .values()
+        .map(Entry::record)
+        .filter(|record| record.spawned_at < cutoff)
+        .filter(|record| record.leave_queue(TaskState::Cancelled))
+        .count()
+}
+
+// Removes the record once the future is gone, whether it finished or got cancelled
+struct Registration<'a>(&'a TaskRecord);
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        #[cfg(feature = "task-registry")]
+        {
+            if let Ok(mut tasks) = TASKS.lock() {
+                tasks.remove(&self.0.id);
+            }
+            let waiters = WAITERS
+                .lock()
+                .ok()
+                .and_then(|mut waiters| waiters.as_mut()?.remove(&self.0.id));
+            waiters.into_iter().flatten().for_each(Waker::wake);
+        }
+        // taking the lock makes sure a wait_idle that saw the task still live is waiting
+        if LIVE.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let _lock = EMPTY_LOCK.lock();
+            EMPTY.notify_all();
+        }
+        if self.0.detached.swap(2, Ordering::AcqRel) == 1 {
+            detached::released();
+        }
+    }
+}
+
+pin_project! {
+    // Wraps every spawned future so each poll is counted and the state flips to running
+    // for its duration. The schedule closure flips it back to queued when the task is woken.
+    // The record it borrows is the task's own metadata, which async_task keeps in place for as
+    // long as the future is around.
+    pub(crate) struct Tracked<'a, F> {
+        #[pin]
+        future: F,
+        registration: Registration<'a>,
+        // wakes the task at its deadline, if it has one
+        expiry: Option<Box<Sleep>>,
+        log_context: LogContext,
+    }
+}
+
+impl<'a, F> Tracked<'a, F> {
+    pub(crate) fn new(future: F, record: &'a TaskRecord) -> Self {
+        Self {
+            future,
+            expiry: record
+                .deadline
+                .as_ref()
+                .map(|deadline| Box::new(sleep_until(deadline.at))),
+            log_context: log_context::capture(),
+            registration: Registration(record),
+        }
+    }
+}
+
+impl<F: Future> Future for Tracked<'_, F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let record = this.registration.0;
+        if let Some(expiry) = this.expiry
+            && Pin::new(&mut **expiry).poll(cx).is_ready()
+        {
+            // unwinding is the one way to end a task without an output; the future is dropped
+            // with it and the Task reports the task as aborted
+            if let Some(deadline) = &record.deadline {
+                deadline.passed.store(true, Ordering::Release);
+            }
+            std::panic::resume_unwind(Box::new(Expired));
+        }
+        #[cfg(feature = "task-registry")]
+        {
+            record.polls.fetch_add(1, Ordering::Relaxed);
+            record.set_state(TaskState::Running);
+        }
+        let started = watchdog::poll_started();
+        #[cfg(feature = "metrics")]
+        let label_clock = (!record.label_counters.is_empty()).then(Instant::now);
+        let _polling = Polling(CURRENT_DEPTH.replace(Some(record.depth)));
+        let _log_context = log_context::enter(this.log_context);
+        let _tick = coop::tick();
+        let watch = panics::PanicWatch;
+        let poll = this.future.poll(cx);
+        drop(watch);
+        watchdog::poll_finished(record, started);
+        #[cfg(feature = "metrics")]
+        {
+            metrics::polled(poll.is_ready());
+            if let Some(label_clock) = label_clock {
+                metrics::label_polled(
+                    &record.label_counters,
+                    label_clock.elapsed(),
+                    poll.is_ready(),
+                );
+            }
+        }
+        #[cfg(feature = "task-registry")]
+        {
+            let polled_for = record.spawned_at.elapsed().as_nanos() as u64;
+            record.last_polled.store(polled_for + 1, Ordering::Relaxed);
+            record.set_state(TaskState::Idle);
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_lite::FutureExt;
+    use futures_lite::future::Boxed;
+
+    use super::*;
+    #[cfg(feature = "task-registry")]
+    use crate::spawn_named_task;
+    use crate::test_support::runtime;
+    use crate::testing::BackgroundFuture;
+    use crate::{JoinError, Runtime, TaskExt, spawn_task, spawn_with_ttl};
+    #[cfg(all(feature = "task-registry", feature = "metrics"))]
+    use crate::{Tenant, spawn_for_tenant, spawn_with_labels};
+
+    // Spawns the next level down to `levels` and awaits it. Lists, level by level, whether the
+    // spawned child had already run to the end by the time spawn_task returned.
+    fn recurse(level: u32, levels: u32) -> Boxed<Vec<bool>> {
+        async move {
+            if level == levels {
+                return Vec::new();
+            }
+            let child = spawn_task(recurse(level + 1, levels), FutureType::High);
+            let ran_inline = child.is_finished();
+            let mut below = child.await;
+            below.insert(0, ran_inline);
+            below
+        }
+        .boxed()
+    }
+
+    #[cfg(feature = "task-registry")]
+    #[test]
+    fn live_tasks_lists_spawned_tasks_until_they_finish() {
+        let _runtime = runtime(Runtime::new());
+        let (release, released) = flume::unbounded::<()>();
+        let line = line!() + 1;
+        let waiting = spawn_named_task("waiting", released.into_recv_async(), FutureType::Low);
+        let ready = spawn_named_task("ready", async { 7 }, FutureType::High);
+        assert_eq!(futures_lite::future::block_on(ready), 7);
+
+        let waiting_id = waiting.metadata().id();
+        // give the worker time to poll it and leave it waiting
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let info = loop {
+            let tasks = Runtime::live_tasks();
+            let info = tasks
+                .into_iter()
+                .find(|task| task.id == waiting_id)
+                .unwrap();
+            if info.state == TaskState::Idle || Instant::now() > deadline {
+                break info;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(info.name.as_deref(), Some("waiting"));
+        assert_eq!(info.priority, FutureType::Low);
+        assert_eq!(info.location.file(), file!());
+        assert_eq!(info.location.line(), line);
+        assert_eq!(info.state, TaskState::Idle);
+        assert_eq!(info.polls, 1);
+        assert!(info.last_polled.is_some());
+        assert!(
+            Runtime::live_tasks()
+                .iter()
+                .all(|task| task.name.as_deref() != Some("ready")),
+            "a finished task is still listed"
+        );
+
+        release.send(()).unwrap();
+        futures_lite::future::block_on(waiting).unwrap();
+        assert!(
+            Runtime::live_tasks()
+                .iter()
+                .all(|task| task.id != waiting_id)
+        );
+    }
+
+    #[test]
+    fn spawns_past_the_max_depth_run_inline() {
+        // with a single worker, a queued child can't run while its parent holds the worker
+        let _runtime = runtime(
+            Runtime::new()
+                .with_high_num(1)
+                .with_low_num(0)
+                .with_max_spawn_depth(2),
+        );
+        let task = spawn_task(recurse(0, 5), FutureType::High);
+        let ran_inline = futures_lite::future::block_on(task);
+        // children at depth 1 and 2 are queued, the ones at depth 3 to 5 run in place
+        assert_eq!(ran_inline, [false, false, true, true, true]);
+    }
+
+    #[cfg(all(feature = "task-registry", feature = "metrics"))]
+    #[test]
+    fn live_tasks_and_metrics_by_label() {
+        let _runtime = runtime(Runtime::new());
+        let (release, released) = flume::unbounded::<()>();
+        let mut tasks: Vec<_> = [("acme", "upload"), ("globex", "upload"), ("acme", "resize")]
+            .into_iter()
+            .map(|(tenant, kind)| {
+                let released = released.clone();
+                let wait = async move { released.recv_async().await.unwrap() };
+                spawn_with_labels(wait, FutureType::Low, [("tenant", tenant), ("kind", kind)])
+            })
+            .collect();
+        // Tenant tasks are labelled with the tenant's name
+        let initech = Tenant::new("initech", Duration::from_secs(1), Duration::from_secs(1));
+        let wait = {
+            let released = released.clone();
+            async move { released.recv_async().await.unwrap() }
+        };
+        tasks.push(spawn_for_tenant(&initech, wait, FutureType::High));
+
+        let tenant_tasks = |tenant: &str| {
+            Runtime::live_tasks()
+                .into_iter()
+                .filter(|task| task.label("tenant") == Some(tenant))
+                .count()
+        };
+        assert_eq!(tenant_tasks("acme"), 2);
+        assert_eq!(tenant_tasks("globex"), 1);
+        assert_eq!(tenant_tasks("initech"), 1);
+
+        for _ in &tasks {
+            release.send(()).unwrap();
+        }
+        futures_lite::future::block_on(crate::join_all(tasks));
+        assert_eq!(tenant_tasks("acme"), 0);
+        let metrics = Runtime::take_metrics();
+        let acme = metrics.label("tenant", "acme");
+        assert_eq!((acme.spawned(), acme.completed()), (2, 2));
+        assert!(acme.polls() >= 2);
+        assert_eq!(metrics.label("kind", "upload").completed(), 2);
+        assert_eq!(metrics.label("tenant", "initech").completed(), 1);
+        assert_eq!(metrics.label("tenant", "nobody").spawned(), 0);
+        // labels no task carries anymore are gone once taken
+        assert_eq!(Runtime::take_metrics().labels().count(), 0);
+    }
+
+    #[test]
+    fn task_outliving_its_ttl_is_aborted() {
+        let ttl = Duration::from_millis(50);
+        let _runtime = runtime(Runtime::new().with_task_ttl(Duration::from_millis(150)));
+        let started = Instant::now();
+        let own = spawn_with_ttl(BackgroundFuture::new(), FutureType::High, ttl);
+        let defaulted = spawn_task(BackgroundFuture::new(), FutureType::Low);
+        assert_eq!(
+            futures_lite::future::block_on(own.join()),
+            Err(JoinError::Ttl)
+        );
+        let own_ended = started.elapsed();
+        assert!(own_ended >= ttl, "aborted after {own_ended:?}");
+        // the runtime's default applies to tasks spawned without a TTL of their own
+        assert_eq!(
+            futures_lite::future::block_on(defaulted.join()),
+            Err(JoinError::Ttl)
+        );
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert!(own_ended < Duration::from_millis(150));
+    }
+
+    #[cfg(feature = "task-registry")]
+    #[test]
+    fn join_ids_waits_for_detached_tasks() {
+        let _runtime = runtime(Runtime::new());
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        let ids: Vec<_> = [30, 10, 20]
+            .into_iter()
+            .map(|millis| {
+                let finished = finished.clone();
+                let task = spawn_task(
+                    async move {
+                        crate::timer::sleep(Duration::from_millis(millis)).await;
+                        finished.lock().unwrap().push(millis);
+                    },
+                    FutureType::Low,
+                );
+                let id = task.metadata().id();
+                task.detach();
+                id
+            })
+            .collect();
+        futures_lite::future::block_on(Runtime::join_ids(ids.clone()));
+        assert_eq!(*finished.lock().unwrap(), [10, 20, 30]);
+        // ids of tasks that are long gone count as done
+        futures_lite::future::block_on(Runtime::join_ids(ids));
+    }
+}
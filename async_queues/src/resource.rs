@@ -0,0 +1,89 @@
+use std::future::Future;
+
+// Hands the resource back to `release` when dropped, however the body ended
+struct Held<R, F: FnOnce(R)> {
+    resource: Option<R>,
+    release: Option<F>,
+}
+
+impl<R, F: FnOnce(R)> Drop for Held<R, F> {
+    fn drop(&mut self) {
+        if let (Some(resource), Some(release)) = (self.resource.take(), self.release.take()) {
+            release(resource);
+        }
+    }
+}
+
+// Acquire a resource with `acquire`, run `body` with it, then pass it to `release`, e.g. to
+// return a connection to its pool or roll back an uncommitted transaction. Release happens
+// however `body` ends: completing, panicking, or being dropped mid-way because the task was
+// cancelled or lost a race. `release` runs synchronously on whichever thread that happens,
+// so keep it short; spawn from it to do async cleanup. Nothing is released if `acquire` never
+// completes.
+pub async fn with_resource<A, R, B, T, F>(acquire: A, body: B, release: F) -> T
+where
+    A: Future<Output = R>,
+    B: AsyncFnOnce(&mut R) -> T,
+    F: FnOnce(R),
+{
+    let mut held = Held {
+        resource: Some(acquire.await),
+        release: Some(release),
+    };
+    body(held.resource.as_mut().unwrap()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::timer::sleep;
+    use crate::{FutureType, Runtime, spawn_cancellable, spawn_task};
+
+    // ids of the connections not in use
+    type Pool = Arc<Mutex<Vec<u32>>>;
+
+    // Takes a connection out of `pool` for `work`, and puts it back afterwards
+    async fn with_connection(pool: Pool, work: Duration) -> u32 {
+        let taken = pool.clone();
+        with_resource(
+            async move { taken.lock().unwrap().pop().unwrap() },
+            async move |connection: &mut u32| {
+                sleep(work).await;
+                *connection
+            },
+            move |connection| pool.lock().unwrap().push(connection),
+        )
+        .await
+    }
+
+    #[test]
+    fn resource_is_released_when_the_task_is_cancelled() {
+        let _runtime = runtime(Runtime::new());
+        let pool = Pool::new(Mutex::new(vec![7]));
+
+        let used = spawn_task(
+            with_connection(pool.clone(), Duration::from_millis(5)),
+            FutureType::Low,
+        );
+        assert_eq!(futures_lite::future::block_on(used), 7);
+        assert_eq!(*pool.lock().unwrap(), [7]);
+
+        let (task, handle) = spawn_cancellable(
+            with_connection(pool.clone(), Duration::from_secs(60)),
+            FutureType::Low,
+        );
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !pool.lock().unwrap().is_empty() {
+            assert!(Instant::now() < deadline, "the connection was never taken");
+            thread::sleep(Duration::from_millis(1));
+        }
+        handle.cancel();
+        assert_eq!(futures_lite::future::block_on(task), None);
+        assert_eq!(*pool.lock().unwrap(), [7]);
+    }
+}
@@ -0,0 +1,174 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::backoff::Backoff;
+use crate::timer::sleep;
+
+// A pool of retries shared by every `retry` it is handed to: together they get at most
+// `retries` per `window`. Once it is spent, a failed attempt is returned as is instead of
+// retried until the next window starts, so a widespread outage doesn't turn into a retry
+// storm on top of it. Clones share the same budget.
+#[derive(Clone)]
+pub struct RetryBudget {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    retries: u32,
+    window: Duration,
+    usage: Mutex<Usage>,
+}
+
+// Retries spent in the window that started at `since`
+struct Usage {
+    since: Instant,
+    spent: u32,
+}
+
+impl RetryBudget {
+    pub fn new(retries: u32, window: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                retries,
+                window: window.max(Duration::from_millis(1)),
+                usage: Mutex::new(Usage {
+                    since: Instant::now(),
+                    spent: 0,
+                }),
+            }),
+        }
+    }
+
+    // Retries left in the current window
+    pub fn remaining(&self) -> u32 {
+        let mut usage = self.inner.usage.lock().unwrap();
+        self.roll_over(&mut usage);
+        self.inner.retries.saturating_sub(usage.spent)
+    }
+
+    // Takes one retry out of the budget, false if there is none left in this window
+    pub fn try_spend(&self) -> bool {
+        let mut usage = self.inner.usage.lock().unwrap();
+        self.roll_over(&mut usage);
+        if usage.spent >= self.inner.retries {
+            return false;
+        }
+        usage.spent += 1;
+        true
+    }
+
+    fn roll_over(&self, usage: &mut Usage) {
+        let now = Instant::now();
+        if now >= usage.since + self.inner.window {
+            usage.since = now;
+            usage.spent = 0;
+        }
+    }
+}
+
+// How `retry` retries a failing operation. No delay and no shared budget by default, e.g.
+// `RetryPolicy::new(3).with_backoff(Duration::from_millis(10), Duration::from_secs(1))
+// .with_budget(budget.clone())`.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    backoff: Backoff,
+    budget: Option<RetryBudget>,
+}
+
+impl RetryPolicy {
+    // Give up after `max_retries` retries, i.e. `max_retries + 1` attempts
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            backoff: Backoff::default(),
+            budget: None,
+        }
+    }
+
+    // Space the attempts out: `initial` between the first failure and the first retry, then
+    // twice the previous gap before each further retry, never more than `max`
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = Backoff::new(initial, max);
+        self
+    }
+
+    // Every retry also has to be paid for out of `budget`
+    pub fn with_budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+}
+
+// Run the future built by `factory` until it returns Ok, building a fresh one for every
+// attempt. The last Err is returned once `policy` runs out of retries or its budget is spent.
+pub async fn retry<F, Fut, T, E>(policy: RetryPolicy, mut factory: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut retries = 0;
+    loop {
+        let error = match factory().await {
+            Ok(output) => return Ok(output),
+            Err(error) => error,
+        };
+        if retries >= policy.max_retries
+            || policy
+                .budget
+                .as_ref()
+                .is_some_and(|budget| !budget.try_spend())
+        {
+            return Err(error);
+        }
+        retries += 1;
+        let delay = policy.backoff.delay(retries);
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{FutureType, Runtime, join_all, spawn_task};
+
+    #[test]
+    fn shared_budget_stops_retries_across_operations() {
+        let _runtime = runtime(Runtime::new());
+        let budget = RetryBudget::new(3, Duration::from_secs(60));
+        let attempts = Arc::new(AtomicU32::new(0));
+        let failing = || {
+            let policy = RetryPolicy::new(5)
+                .with_backoff(Duration::from_millis(1), Duration::from_millis(4))
+                .with_budget(budget.clone());
+            let attempts = attempts.clone();
+            spawn_task(
+                retry(policy, move || {
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    async { Err::<(), _>("unavailable") }
+                }),
+                FutureType::Low,
+            )
+        };
+        let outcomes = futures_lite::future::block_on(join_all((0..3).map(|_| failing())));
+        assert_eq!(outcomes, [Err("unavailable"); 3]);
+        // a first attempt each, and only the three retries the budget had between them
+        assert_eq!(attempts.load(Ordering::SeqCst), 6);
+        assert_eq!(budget.remaining(), 0);
+
+        // with the budget spent, the next failure isn't retried at all
+        attempts.store(0, Ordering::SeqCst);
+        assert_eq!(
+            futures_lite::future::block_on(failing()),
+            Err("unavailable")
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,17 @@
+use std::sync::Mutex;
+
+// The generator behind every random decision the runtime makes. Workers share it rather than
+// each keeping their own, so that Runtime::with_rng_seed pins down the whole sequence of
+// picks, not one sequence per thread. Seeded randomly on first use otherwise.
+static RNG: Mutex<Option<fastrand::Rng>> = Mutex::new(None);
+
+pub(crate) fn set_seed(seed: Option<u64>) {
+    *RNG.lock().unwrap() = seed.map(fastrand::Rng::with_seed);
+}
+
+pub(crate) fn f64() -> f64 {
+    RNG.lock()
+        .unwrap()
+        .get_or_insert_with(fastrand::Rng::new)
+        .f64()
+}
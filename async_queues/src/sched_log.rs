@@ -0,0 +1,209 @@
+use std::fmt;
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::Instant;
+
+use crate::{FutureType, TaskId};
+
+// The labels a task was spawned with, shared with its record rather than copied per event
+pub type Labels = Arc<[(String, String)]>;
+
+// One line of the scheduler trace, only built with the `scheduler-log` feature.
+// Workers are numbered in the order they were started, high pool first, and events about a
+// task carry the labels it was spawned with.
+#[derive(Clone, Debug)]
+pub enum SchedulerEvent {
+    Enqueue {
+        at: Instant,
+        task: TaskId,
+        labels: Labels,
+        queue: FutureType,
+    },
+    Dequeue {
+        at: Instant,
+        task: TaskId,
+        labels: Labels,
+        worker: usize,
+        queue: FutureType,
+    },
+    // a worker found its own queue empty and took a task from the other one
+    Steal {
+        at: Instant,
+        task: TaskId,
+        labels: Labels,
+        worker: usize,
+        from: FutureType,
+    },
+    // a worker found both queues empty and went to sleep
+    Park {
+        at: Instant,
+        worker: usize,
+    },
+    Unpark {
+        at: Instant,
+        worker: usize,
+    },
+}
+
+// timestamps print as the offset from when logging was first used
+static EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+fn offset(at: &Instant) -> std::time::Duration {
+    at.saturating_duration_since(*EPOCH)
+}
+
+// ` {tenant=acme, kind=upload}` after the task id, nothing for an unlabelled task
+struct ShowLabels<'a>(&'a Labels);
+
+impl fmt::Display for ShowLabels<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            let separator = if i == 0 { " {" } else { ", " };
+            write!(f, "{separator}{key}={value}")?;
+        }
+        if !self.0.is_empty() {
+            f.write_str("}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SchedulerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Enqueue {
+                at,
+                task,
+                labels,
+                queue,
+            } => write!(
+                f,
+                "+{:?} enqueue {task}{} -> {queue:?}",
+                offset(at),
+                ShowLabels(labels)
+            ),
+            Self::Dequeue {
+                at,
+                task,
+                labels,
+                worker,
+                queue,
+            } => write!(
+                f,
+                "+{:?} dequeue {task}{} <- {queue:?} on worker {worker}",
+                offset(at),
+                ShowLabels(labels)
+            ),
+            Self::Steal {
+                at,
+                task,
+                labels,
+                worker,
+                from,
+            } => write!(
+                f,
+                "+{:?} steal {task}{} from {from:?} on worker {worker}",
+                offset(at),
+                ShowLabels(labels)
+            ),
+            Self::Park { at, worker } => write!(f, "+{:?} park worker {worker}", offset(at)),
+            Self::Unpark { at, worker } => write!(f, "+{:?} unpark worker {worker}", offset(at)),
+        }
+    }
+}
+
+pub(crate) type Sink = Arc<dyn Fn(&SchedulerEvent) + Send + Sync>;
+
+static SINK: RwLock<Option<Sink>> = RwLock::new(None);
+
+pub(crate) fn set_sink(sink: Option<Sink>) {
+    LazyLock::force(&EPOCH);
+    *SINK.write().unwrap() = sink;
+}
+
+pub(crate) fn emit(event: impl FnOnce(Instant) -> SchedulerEvent) {
+    if let Some(sink) = SINK.read().unwrap().as_ref() {
+        sink(&event(Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::testing::PollCountFuture;
+    use crate::{Runtime, spawn_task, spawn_with_labels};
+
+    #[test]
+    fn log_shows_each_enqueue_and_dequeue_of_a_task() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let _runtime = runtime(
+            Runtime::deterministic_pair()
+                .with_scheduler_log(move |event| sink.lock().unwrap().push(event.clone())),
+        );
+        let task = spawn_task(PollCountFuture::new(2), FutureType::Low);
+        let id = task.metadata().id();
+        assert_eq!(futures_lite::future::block_on(task), 2);
+
+        let events = events.lock().unwrap();
+        let trace: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                SchedulerEvent::Enqueue { task, queue, .. } if *task == id => {
+                    Some(format!("enqueue {queue:?}"))
+                }
+                SchedulerEvent::Dequeue { task, queue, .. } if *task == id => {
+                    Some(format!("dequeue {queue:?}"))
+                }
+                SchedulerEvent::Steal { task, .. } if *task == id => Some("steal".to_string()),
+                _ => None,
+            })
+            .collect();
+        // spawned, then woken by itself after the first poll
+        assert_eq!(
+            trace,
+            ["enqueue Low", "dequeue Low", "enqueue Low", "dequeue Low"]
+        );
+        // the high worker had nothing to do
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, SchedulerEvent::Park { .. }))
+        );
+    }
+
+    #[test]
+    fn events_carry_the_task_labels() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let _runtime = runtime(
+            Runtime::deterministic_pair()
+                .with_scheduler_log(move |event| sink.lock().unwrap().push(event.clone())),
+        );
+        let labelled = spawn_with_labels(async {}, FutureType::High, [("kind", "upload")]);
+        let id = labelled.metadata().id();
+        futures_lite::future::block_on(labelled);
+
+        let events = events.lock().unwrap();
+        let enqueue = events
+            .iter()
+            .find(|event| matches!(event, SchedulerEvent::Enqueue { task, .. } if *task == id))
+            .unwrap();
+        let SchedulerEvent::Enqueue { labels, .. } = enqueue else {
+            unreachable!()
+        };
+        assert_eq!(labels[..], [("kind".to_string(), "upload".to_string())]);
+        let shown = enqueue.to_string();
+        assert!(
+            shown.ends_with(&format!("enqueue {id} {{kind=upload}} -> High")),
+            "{shown}"
+        );
+        let dequeued = events.iter().any(|event| {
+            matches!(event, SchedulerEvent::Dequeue { task, labels, .. }
+                if *task == id && labels.len() == 1)
+        });
+        assert!(dequeued);
+    }
+}
@@ -0,0 +1,159 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::panic::{AssertUnwindSafe, Location, catch_unwind, resume_unwind};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll};
+
+use pin_project_lite::pin_project;
+
+use crate::{FutureType, TaskRunnable, panics, registry, route, schedule_high, schedule_low};
+
+// Handed to the closure passed to `scope`, for spawning tasks that borrow from the caller.
+// The lifetimes work like std::thread::Scope's: 'env is everything the tasks may borrow,
+// 'scope the scope itself.
+pub struct Scope<'scope, 'env: 'scope> {
+    state: Arc<State>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+// Scoped tasks whose future hasn't been dropped yet
+#[derive(Default)]
+struct State {
+    running: Mutex<usize>,
+    done: Condvar,
+    panicked: Mutex<bool>,
+}
+
+// Run `f`, which can spawn tasks borrowing anything that outlives this call, e.g. each task
+// filling its own `&mut` element of a Vec the caller owns. No Arc or Mutex is needed: the
+// borrow checker keeps the tasks from aliasing, and scope blocks until every task spawned in
+// it has finished and been dropped, so the borrows end before it returns. If a task
+// panicked, scope panics too once they are all done. It blocks the calling thread like join!,
+// so call it from outside the runtime.
+pub fn scope<'env, F, R>(f: F) -> R
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+{
+    let scope = Scope {
+        state: Arc::new(State::default()),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+    // the tasks may still be running when `f` unwinds, so wait for them either way
+    let result = catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    let mut running = scope.state.running.lock().unwrap();
+    while *running > 0 {
+        running = scope.state.done.wait(running).unwrap();
+    }
+    drop(running);
+    match result {
+        Err(payload) => resume_unwind(payload),
+        Ok(_) if *scope.state.panicked.lock().unwrap() => panic!("a scoped task panicked"),
+        Ok(result) => result,
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    // Spawn `future` on the runtime; it may borrow anything that outlives the scope
+    #[track_caller]
+    pub fn spawn<F>(&'scope self, future: F, order: FutureType)
+    where
+        F: Future<Output = ()> + Send + 'scope,
+    {
+        *self.state.running.lock().unwrap() += 1;
+        let future = Scoped {
+            future,
+            done: Done(self.state.clone()),
+        };
+        let order = route(order);
+        let schedule = match order {
+            FutureType::High => schedule_high as fn(TaskRunnable),
+            FutureType::Low => schedule_low,
+        };
+        let record = registry::new_record(None, Vec::new(), None, order, Location::caller());
+        // SAFETY: the future only borrows data outliving 'scope, and `scope` doesn't return
+        // before every scoped future has been dropped, which Done reports. The record is the
+        // task's own metadata, which outlives the future.
+        let (runnable, task) = unsafe {
+            async_task::Builder::new()
+                .metadata(record)
+                .propagate_panic(panics::captured_by_task())
+                .spawn_unchecked(|record| registry::Tracked::new(future, record), schedule)
+        };
+        registry::register(runnable.metadata());
+        task.detach();
+        runnable.schedule();
+    }
+}
+
+pin_project! {
+    // `done` is declared after `future`, so it is dropped after it
+    struct Scoped<F> {
+        #[pin]
+        future: F,
+        done: Done,
+    }
+}
+
+impl<F: Future<Output = ()>> Future for Scoped<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.project();
+        // noted here, the panic policy decides what happens to the panic itself
+        catch_unwind(AssertUnwindSafe(|| this.future.poll(cx))).unwrap_or_else(|payload| {
+            *this.done.0.panicked.lock().unwrap() = true;
+            resume_unwind(payload)
+        })
+    }
+}
+
+// Dropped with the task's future, whether it finished, panicked or was cancelled
+struct Done(Arc<State>);
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        let mut running = self.0.running.lock().unwrap();
+        *running -= 1;
+        if *running == 0 {
+            self.0.done.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::Runtime;
+    use crate::test_support::runtime;
+    use crate::timer::sleep;
+
+    #[test]
+    fn scoped_tasks_fill_a_borrowed_vec() {
+        let _runtime = runtime(Runtime::new());
+        let inputs = [3, 1, 4, 1, 5, 9, 2, 6];
+        let mut outputs = vec![0; inputs.len()];
+        scope(|scope| {
+            for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+                let order = if input % 2 == 0 {
+                    FutureType::High
+                } else {
+                    FutureType::Low
+                };
+                scope.spawn(
+                    async move {
+                        // later elements may well be filled first
+                        sleep(Duration::from_millis(10 - input)).await;
+                        *output = input * 10;
+                    },
+                    order,
+                );
+            }
+        });
+        assert_eq!(outputs, [30, 10, 40, 10, 50, 90, 20, 60]);
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::marker::PhantomData;
+use std::panic::Location;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::FutureType;
+
+// A unit of work that can be written out and read back, so a SpillQueue can park it on disk
+// instead of holding it as a future. Only the description of the work is stored; `run` builds
+// the future once it is its turn.
+pub trait SerializableTask: Sized + Send + 'static {
+    fn serialize(&self) -> Vec<u8>;
+    fn deserialize(bytes: &[u8]) -> io::Result<Self>;
+    fn run(self) -> impl Future<Output = ()> + Send + 'static;
+}
+
+// Spawns SerializableTasks, but only keeps up to `max_in_memory` of them spawned at a time.
+// Past that, pushed tasks are serialized into files in `dir`, one per task, and every task
+// that finishes brings the oldest spilled one back and spawns it, so a batch of millions of
+// tasks doesn't have to fit in memory. Spilled tasks run in the order they were pushed.
+// Clones share the same queue.
+pub struct SpillQueue<T> {
+    inner: Arc<Inner>,
+    task: PhantomData<fn(T)>,
+}
+
+impl<T> Clone for SpillQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            task: PhantomData,
+        }
+    }
+}
+
+struct Inner {
+    dir: PathBuf,
+    max_in_memory: usize,
+    order: FutureType,
+    location: &'static Location<'static>,
+    state: Mutex<State>,
+}
+
+// Files are numbered in push order; `next_read..next_write` are the ones on disk
+struct State {
+    in_memory: usize,
+    next_read: u64,
+    next_write: u64,
+    failed: usize,
+}
+
+impl<T: SerializableTask> SpillQueue<T> {
+    // Spill into `dir`, which is created if needed. Files left there by an earlier queue aren't
+    // picked up.
+    #[track_caller]
+    pub fn new(
+        dir: impl Into<PathBuf>,
+        max_in_memory: usize,
+        order: FutureType,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                dir,
+                max_in_memory: max_in_memory.max(1),
+                order,
+                location: Location::caller(),
+                state: Mutex::new(State {
+                    in_memory: 0,
+                    next_read: 0,
+                    next_write: 0,
+                    failed: 0,
+                }),
+            }),
+            task: PhantomData,
+        })
+    }
+
+    // Spawn `task`, or write it to disk if the queue is at `max_in_memory` already
+    pub fn push(&self, task: T) -> io::Result<()> {
+        let mut state = self.inner.state.lock().unwrap();
+        // while anything is on disk, new tasks queue up behind it
+        if state.in_memory >= self.inner.max_in_memory || state.next_read < state.next_write {
+            fs::write(self.inner.path(state.next_write), task.serialize())?;
+            state.next_write += 1;
+            return Ok(());
+        }
+        state.in_memory += 1;
+        drop(state);
+        self.spawn(task);
+        Ok(())
+    }
+
+    // Tasks spawned and not finished yet
+    pub fn in_memory(&self) -> usize {
+        self.inner.state.lock().unwrap().in_memory
+    }
+
+    // Tasks waiting on disk
+    pub fn spilled(&self) -> usize {
+        let state = self.inner.state.lock().unwrap();
+        (state.next_write - state.next_read) as usize
+    }
+
+    // Spilled tasks that couldn't be read back from disk, and were dropped without running
+    pub fn failed(&self) -> usize {
+        self.inner.state.lock().unwrap().failed
+    }
+
+    fn spawn(&self, task: T) {
+        let slot = Slot(self.clone());
+        let future = async move {
+            let _slot = slot;
+            task.run().await;
+        };
+        crate::spawn(future, self.inner.order, None, self.inner.location).detach();
+    }
+
+    // A task's slot is free: hand it to the oldest spilled task that can be read back. One
+    // that can't is counted in `failed` and skipped, there is nobody to return the error to.
+    fn finished(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        while state.next_read < state.next_write {
+            let path = self.inner.path(state.next_read);
+            state.next_read += 1;
+            let task = fs::read(&path).and_then(|bytes| T::deserialize(&bytes));
+            let _ = fs::remove_file(&path);
+            let Ok(task) = task else {
+                state.failed += 1;
+                continue;
+            };
+            drop(state);
+            self.spawn(task);
+            return;
+        }
+        state.in_memory -= 1;
+    }
+}
+
+// Frees the task's slot when its future is dropped, whether it finished, panicked or was
+// cancelled
+struct Slot<T: SerializableTask>(SpillQueue<T>);
+
+impl<T: SerializableTask> Drop for Slot<T> {
+    fn drop(&mut self) {
+        self.0.finished();
+    }
+}
+
+impl Inner {
+    fn path(&self, index: u64) -> PathBuf {
+        self.dir.join(format!("{index}.task"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Runtime;
+    use crate::test_support::runtime;
+
+    // Numbers the spilled tasks have run with
+    static RAN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+    struct Record(u32);
+
+    impl SerializableTask for Record {
+        fn serialize(&self) -> Vec<u8> {
+            self.0.to_le_bytes().to_vec()
+        }
+
+        fn deserialize(bytes: &[u8]) -> io::Result<Self> {
+            let bytes = bytes.try_into().map_err(io::Error::other)?;
+            Ok(Self(u32::from_le_bytes(bytes)))
+        }
+
+        async fn run(self) {
+            RAN.lock().unwrap().push(self.0);
+        }
+    }
+
+    #[test]
+    fn spilled_tasks_all_run() {
+        // no workers yet, so the first four stay spawned and the rest go to disk
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let dir = std::env::temp_dir().join(format!("async_queues-spill-{}", std::process::id()));
+        let queue = SpillQueue::new(&dir, 4, FutureType::Low).unwrap();
+        for i in 0..50 {
+            queue.push(Record(i)).unwrap();
+        }
+        assert_eq!((queue.in_memory(), queue.spilled()), (4, 46));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 46);
+
+        Runtime::graceful_restart(Runtime::new());
+        Runtime::wait_idle();
+        let mut ran = std::mem::take(&mut *RAN.lock().unwrap());
+        ran.sort_unstable();
+        assert_eq!(ran, (0..50).collect::<Vec<_>>());
+        assert_eq!((queue.in_memory(), queue.spilled()), (0, 0));
+        assert_eq!(queue.failed(), 0);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_spilled_tasks_are_counted_and_skipped() {
+        let _runtime = runtime(Runtime::new().with_high_num(0).with_low_num(0));
+        let dir =
+            std::env::temp_dir().join(format!("async_queues-spill-failed-{}", std::process::id()));
+        let queue = SpillQueue::new(&dir, 1, FutureType::Low).unwrap();
+        for i in 0..5 {
+            queue.push(Record(i)).unwrap();
+        }
+        // task 2 is the second one on disk; three bytes don't make a Record
+        fs::write(dir.join("1.task"), [0, 0, 0]).unwrap();
+
+        Runtime::graceful_restart(Runtime::new());
+        Runtime::wait_idle();
+        let mut ran = std::mem::take(&mut *RAN.lock().unwrap());
+        ran.sort_unstable();
+        assert_eq!(ran, [0, 1, 3, 4]);
+        assert_eq!(queue.failed(), 1);
+        assert_eq!((queue.in_memory(), queue.spilled()), (0, 0));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_lite::Stream;
+
+use crate::{blocking, coop};
+
+// Turn a blocking iterator (db cursor, file lines, ...) into a Stream.
+// The iterator runs on the blocking pool and hands items over a one-slot channel, which keeps
+// it at most one item ahead of the consumer. Dropping the stream stops it at its next item.
+// The pool has at most 64 threads and an iterator holds on to one until it ends or the stream
+// is dropped; past that, new iterators wait for a thread, so don't let more than 64 streams
+// wait on each other.
+pub fn stream_from_iter<I>(iter: I) -> impl Stream<Item = I::Item> + Send + Unpin
+where
+    I: IntoIterator + Send + 'static,
+    I::Item: Send + 'static,
+{
+    let (sender, receiver) = flume::bounded(1);
+    blocking::spawn(move || {
+        for item in iter {
+            if sender.send(item).is_err() {
+                break;
+            }
+        }
+    });
+    let mut items = receiver.into_stream();
+    futures_lite::stream::poll_fn(move |cx| {
+        if coop::poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
+        Pin::new(&mut items).poll_next(cx)
+    })
+}
+
+// Items of several streams in one, see `merge`
+pub struct Merge<S> {
+    streams: Vec<Option<S>>,
+    // where the next poll starts looking, so one busy stream can't starve the rest
+    next: usize,
+}
+
+// Interleave the items of `streams` in the order they become ready, not round-robin: a fast
+// stream can yield many items in a row. Ends once every stream has ended.
+pub fn merge<S>(streams: Vec<S>) -> Merge<S>
+where
+    S: Stream + Unpin,
+{
+    Merge {
+        streams: streams.into_iter().map(Some).collect(),
+        next: 0,
+    }
+}
+
+impl<S: Stream + Unpin> Stream for Merge<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let this = &mut *self;
+        let len = this.streams.len();
+        let mut pending = false;
+        for offset in 0..len {
+            let index = (this.next + offset) % len;
+            let Some(stream) = &mut this.streams[index] else {
+                continue;
+            };
+            match Pin::new(stream).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.next = (index + 1) % len;
+                    return Poll::Ready(Some(item));
+                }
+                Poll::Ready(None) => this.streams[index] = None,
+                Poll::Pending => pending = true,
+            }
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_lite::StreamExt;
+    use futures_lite::stream::{self, Boxed};
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::timer::sleep;
+    use crate::{FutureType, Runtime, spawn_task};
+
+    // `ticks` items (name, tick) one `period` apart
+    fn interval(name: char, period: Duration, ticks: u32) -> Boxed<(char, u32)> {
+        stream::unfold(0, move |tick| async move {
+            if tick == ticks {
+                return None;
+            }
+            sleep(period).await;
+            Some(((name, tick), tick + 1))
+        })
+        .boxed()
+    }
+
+    #[test]
+    fn iterator_items_arrive_in_order() {
+        let _runtime = runtime(Runtime::new());
+        let task = spawn_task(
+            stream_from_iter(0..100).collect::<Vec<_>>(),
+            FutureType::Low,
+        );
+        assert_eq!(
+            futures_lite::future::block_on(task),
+            (0..100).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn merged_intervals_interleave_by_readiness() {
+        let _runtime = runtime(Runtime::new());
+        let fast = interval('f', Duration::from_millis(10), 6);
+        let slow = interval('s', Duration::from_millis(50), 2);
+        let task = spawn_task(merge(vec![slow, fast]).collect::<Vec<_>>(), FutureType::Low);
+        let merged = futures_lite::future::block_on(task);
+        assert_eq!(merged.len(), 8, "{merged:?}");
+        // each stream's items keep their own order
+        for name in ['f', 's'] {
+            let ticks: Vec<_> = merged
+                .iter()
+                .filter(|(from, _)| *from == name)
+                .map(|&(_, tick)| tick)
+                .collect();
+            let expected = if name == 'f' { 6 } else { 2 };
+            assert_eq!(ticks, (0..expected).collect::<Vec<_>>());
+        }
+        // not round-robin: the fast stream got ahead while the slow one was waiting
+        let before_slow = merged.iter().position(|(from, _)| *from == 's').unwrap();
+        assert!(before_slow >= 2, "{merged:?}");
+    }
+}
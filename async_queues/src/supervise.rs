@@ -0,0 +1,165 @@
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, Location};
+use std::time::Duration;
+
+use futures_lite::FutureExt;
+
+use crate::backoff::Backoff;
+use crate::timer::sleep;
+use crate::{FutureType, Task};
+
+// When a supervised future gets started again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Restart {
+    // after it completes as well as after it panics
+    Always,
+    // only after it panics; completing normally ends supervision
+    OnFailure,
+}
+
+// How spawn_supervised restarts its future. No limit and no delay by default, e.g.
+// `RestartPolicy::on_failure().with_max_restarts(3).with_backoff(Duration::from_millis(10),
+// Duration::from_secs(1))`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RestartPolicy {
+    restart: Restart,
+    max_restarts: Option<u32>,
+    backoff: Backoff,
+}
+
+impl RestartPolicy {
+    pub fn always() -> Self {
+        Self::new(Restart::Always)
+    }
+
+    pub fn on_failure() -> Self {
+        Self::new(Restart::OnFailure)
+    }
+
+    fn new(restart: Restart) -> Self {
+        Self {
+            restart,
+            max_restarts: None,
+            backoff: Backoff::default(),
+        }
+    }
+
+    // Give up once the future has been restarted this many times
+    pub fn with_max_restarts(mut self, restarts: u32) -> Self {
+        self.max_restarts = Some(restarts);
+        self
+    }
+
+    // Keep a crash-looping future from spinning: the first restart waits `initial`, and the
+    // wait doubles with every restart after it up to `max`
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = Backoff::new(initial, max);
+        self
+    }
+}
+
+// What the supervisor ended with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Supervised {
+    pub restarts: u32,
+    // true if the last run panicked, i.e. the supervisor ran out of restarts
+    pub failed: bool,
+}
+
+// Spawn the future built by `factory` and start a fresh one from it whenever it finishes or
+// panics, as `policy` says. Every run is its own task at `order`, so a panic is observed
+// through its Task the same way under every PanicPolicy except Abort. Dropping the returned
+// Task stops the supervisor and cancels the run in progress.
+#[track_caller]
+pub fn spawn_supervised<F, Fut>(
+    mut factory: F,
+    policy: RestartPolicy,
+    order: FutureType,
+) -> Task<Supervised>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let location = Location::caller();
+    let supervisor = async move {
+        let mut restarts = 0;
+        loop {
+            let run = crate::spawn(factory(), order, None, location);
+            let failed = AssertUnwindSafe(run).catch_unwind().await.is_err();
+            let restart = failed || policy.restart == Restart::Always;
+            if !restart || policy.max_restarts.is_some_and(|max| restarts >= max) {
+                return Supervised { restarts, failed };
+            }
+            restarts += 1;
+            let delay = policy.backoff.delay(restarts);
+            if !delay.is_zero() {
+                sleep(delay).await;
+            }
+        }
+    };
+    crate::spawn(supervisor, order, None, location)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use futures_lite::future::Boxed;
+
+    use super::*;
+    use crate::Runtime;
+    use crate::test_support::runtime;
+
+    // A factory whose futures panic on their first `panics` runs and complete after that
+    fn flaky(panics: u32) -> (Arc<AtomicU32>, impl FnMut() -> Boxed<()>) {
+        let runs = Arc::new(AtomicU32::new(0));
+        let counted = runs.clone();
+        let factory = move || {
+            let run = counted.fetch_add(1, Ordering::Relaxed);
+            async move {
+                if run < panics {
+                    panic!("run {run} failed")
+                }
+            }
+            .boxed()
+        };
+        (runs, factory)
+    }
+
+    #[test]
+    fn restarts_until_the_future_succeeds() {
+        let _runtime = runtime(Runtime::new());
+        let (runs, factory) = flaky(2);
+        let policy = RestartPolicy::on_failure()
+            .with_max_restarts(3)
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(5));
+        let supervised =
+            futures_lite::future::block_on(spawn_supervised(factory, policy, FutureType::Low));
+        assert_eq!(
+            supervised,
+            Supervised {
+                restarts: 2,
+                failed: false
+            }
+        );
+        assert_eq!(runs.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn gives_up_after_max_restarts() {
+        let _runtime = runtime(Runtime::new());
+        let (runs, factory) = flaky(u32::MAX);
+        let policy = RestartPolicy::on_failure().with_max_restarts(3);
+        let supervised =
+            futures_lite::future::block_on(spawn_supervised(factory, policy, FutureType::High));
+        assert_eq!(
+            supervised,
+            Supervised {
+                restarts: 3,
+                failed: true
+            }
+        );
+        assert_eq!(runs.load(Ordering::Relaxed), 4);
+    }
+}
@@ -0,0 +1,377 @@
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::coop;
+
+// A mutex whose lock() yields instead of blocking the worker thread.
+// Waiters are queued in arrival order. Unlocking with waiters queued hands the lock straight to
+// the first one, so a fresh lock() can't take it from under them and nobody waits forever.
+pub struct AsyncMutex<T> {
+    state: Mutex<LockState>,
+    value: UnsafeCell<T>,
+}
+
+struct LockState {
+    locked: bool,
+    waiters: VecDeque<(usize, Waker)>,
+    // the waiter the lock was handed to on unlock; it stays locked until that one takes it
+    handed_to: Option<usize>,
+    next_key: usize,
+}
+
+unsafe impl<T: Send> Send for AsyncMutex<T> {}
+unsafe impl<T: Send> Sync for AsyncMutex<T> {}
+
+impl<T> AsyncMutex<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            state: Mutex::new(LockState {
+                locked: false,
+                waiters: VecDeque::new(),
+                handed_to: None,
+                next_key: 0,
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> LockFuture<'_, T> {
+        LockFuture {
+            mutex: self,
+            key: None,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<AsyncMutexGuard<'_, T>> {
+        let mut state = self.state.lock().unwrap();
+        if state.locked {
+            return None;
+        }
+        state.locked = true;
+        Some(AsyncMutexGuard::new(self))
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn unlock(&self) {
+        let mut state = self.state.lock().unwrap();
+        match state.waiters.pop_front() {
+            Some((key, waker)) => {
+                state.handed_to = Some(key);
+                waker.wake();
+            }
+            None => state.locked = false,
+        }
+    }
+}
+
+pub struct LockFuture<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+    key: Option<usize>,
+}
+
+impl<'a, T> Future for LockFuture<'a, T> {
+    type Output = AsyncMutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if coop::poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let mutex = self.mutex;
+        let mut state = mutex.state.lock().unwrap();
+        if self.key.is_some() && state.handed_to == self.key {
+            state.handed_to = None;
+            self.key = None;
+            return Poll::Ready(AsyncMutexGuard::new(mutex));
+        }
+        // with waiters queued the lock is never free, unlock hands it over instead
+        if !state.locked {
+            state.locked = true;
+            if let Some(key) = self.key.take() {
+                state.waiters.retain(|(k, _)| *k != key);
+            }
+            return Poll::Ready(AsyncMutexGuard::new(mutex));
+        }
+
+        // still queued from an earlier poll: just refresh the waker
+        if let Some(key) = self.key
+            && let Some(entry) = state.waiters.iter_mut().find(|(k, _)| *k == key)
+        {
+            entry.1 = cx.waker().clone();
+            return Poll::Pending;
+        }
+
+        let key = state.next_key;
+        state.next_key += 1;
+        state.waiters.push_back((key, cx.waker().clone()));
+        self.key = Some(key);
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for LockFuture<'_, T> {
+    fn drop(&mut self) {
+        let Some(key) = self.key else {
+            return;
+        };
+        let mut state = self.mutex.state.lock().unwrap();
+        if state.handed_to == Some(key) {
+            // the lock was handed to us but we gave up before taking it, pass it on
+            state.handed_to = None;
+            drop(state);
+            self.mutex.unlock();
+            return;
+        }
+        state.waiters.retain(|(k, _)| *k != key);
+    }
+}
+
+pub struct AsyncMutexGuard<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> AsyncMutexGuard<'a, T> {
+    fn new(mutex: &'a AsyncMutex<T>) -> Self {
+        Self {
+            mutex,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for AsyncMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for AsyncMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for AsyncMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+// Condition variable for use with AsyncMutex. `wait` gives the lock up while sleeping and
+// takes it back before returning, so the usual `while !condition { guard = cv.wait(guard).await }`
+// loop works as it does with std's Condvar. Spurious wake-ups are possible.
+pub struct AsyncCondvar {
+    waiters: Mutex<VecDeque<Arc<Waiter>>>,
+}
+
+struct Waiter {
+    notified: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl AsyncCondvar {
+    pub fn new() -> Self {
+        Self {
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub async fn wait<'a, T>(&self, guard: AsyncMutexGuard<'a, T>) -> AsyncMutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        // register before unlocking so a notify between the unlock and the first poll isn't lost
+        let waiter = Arc::new(Waiter {
+            notified: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        });
+        self.waiters.lock().unwrap().push_back(waiter.clone());
+        drop(guard);
+
+        Notified {
+            condvar: self,
+            waiter,
+            done: false,
+        }
+        .await;
+        mutex.lock().await
+    }
+
+    pub fn notify_one(&self) {
+        if let Some(waiter) = self.waiters.lock().unwrap().pop_front() {
+            waiter.notify();
+        }
+    }
+
+    pub fn notify_all(&self) {
+        let waiters: Vec<_> = self.waiters.lock().unwrap().drain(..).collect();
+        for waiter in waiters {
+            waiter.notify();
+        }
+    }
+}
+
+impl Default for AsyncCondvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Waiter {
+    fn notify(&self) {
+        self.notified.store(true, Ordering::Release);
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Notified<'a> {
+    condvar: &'a AsyncCondvar,
+    waiter: Arc<Waiter>,
+    done: bool,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.waiter.notified.load(Ordering::Acquire) {
+            *self.waiter.waker.lock().unwrap() = Some(cx.waker().clone());
+            // the notify may have landed while we were storing the waker
+            if !self.waiter.notified.load(Ordering::Acquire) {
+                return Poll::Pending;
+            }
+        }
+        self.done = true;
+        Poll::Ready(())
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut waiters = self.condvar.waiters.lock().unwrap();
+        let queued = waiters.len();
+        waiters.retain(|w| !Arc::ptr_eq(w, &self.waiter));
+        // a notify_one picked us but the wait was cancelled; hand it to the next waiter
+        if waiters.len() == queued
+            && self.waiter.notified.load(Ordering::Acquire)
+            && let Some(next) = waiters.pop_front()
+        {
+            next.notify();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{FutureType, Runtime, join_all, spawn_task};
+
+    const CAPACITY: usize = 2;
+
+    struct Buffer {
+        items: AsyncMutex<VecDeque<u32>>,
+        not_empty: AsyncCondvar,
+        not_full: AsyncCondvar,
+    }
+
+    #[test]
+    fn bounded_buffer_consumer_waits_for_producer() {
+        let _runtime = runtime(Runtime::new());
+        let buffer = Arc::new(Buffer {
+            items: AsyncMutex::new(VecDeque::new()),
+            not_empty: AsyncCondvar::new(),
+            not_full: AsyncCondvar::new(),
+        });
+
+        // spawned first, so it finds the buffer empty and has to wait
+        let consumer = spawn_task(
+            {
+                let buffer = buffer.clone();
+                async move {
+                    let mut received = Vec::new();
+                    while received.len() < 10 {
+                        let mut items = buffer.items.lock().await;
+                        while items.is_empty() {
+                            items = buffer.not_empty.wait(items).await;
+                        }
+                        received.push(items.pop_front().unwrap());
+                        drop(items);
+                        buffer.not_full.notify_one();
+                    }
+                    received
+                }
+            },
+            FutureType::Low,
+        );
+        let producer = spawn_task(
+            {
+                let buffer = buffer.clone();
+                async move {
+                    let mut most = 0;
+                    for item in 0..10 {
+                        let mut items = buffer.items.lock().await;
+                        while items.len() == CAPACITY {
+                            items = buffer.not_full.wait(items).await;
+                        }
+                        items.push_back(item);
+                        most = most.max(items.len());
+                        drop(items);
+                        buffer.not_empty.notify_one();
+                    }
+                    most
+                }
+            },
+            FutureType::High,
+        );
+
+        let most = futures_lite::future::block_on(producer);
+        let received = futures_lite::future::block_on(consumer);
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+        assert!(most <= CAPACITY);
+    }
+
+    #[test]
+    fn unlocking_hands_the_lock_to_waiters_in_order() {
+        let _runtime = runtime(Runtime::new());
+        let mutex = Arc::new(AsyncMutex::new(Vec::new()));
+        let held = mutex.try_lock().unwrap();
+        let waiters: Vec<_> = (0..3)
+            .map(|i| {
+                let shared = mutex.clone();
+                let task = spawn_task(
+                    async move {
+                        shared.lock().await.push(i);
+                    },
+                    FutureType::Low,
+                );
+                // let each queue up on the mutex before the next one is spawned
+                while mutex.state.lock().unwrap().waiters.len() <= i {
+                    std::thread::yield_now();
+                }
+                task
+            })
+            .collect();
+        drop(held);
+        futures_lite::future::block_on(join_all(waiters));
+        assert_eq!(*mutex.try_lock().unwrap(), [0, 1, 2]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use pin_project_lite::pin_project;
+
+use crate::timer::{Sleep, sleep_until};
+use crate::{FutureType, SpawnOrder, Task};
+
+// A group of tasks sharing a CPU budget: together they get at most `budget` of poll time per
+// `window`. Once the budget is used up, the group's tasks are held back until the next window
+// starts, while everyone else's keep running. Clones share the same budget.
+#[derive(Clone)]
+pub struct Tenant {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    name: String,
+    budget: Duration,
+    window: Duration,
+    usage: Mutex<Usage>,
+}
+
+// Poll time spent in the window that started at `since`
+struct Usage {
+    since: Instant,
+    used: Duration,
+}
+
+impl Tenant {
+    pub fn new(name: impl Into<String>, budget: Duration, window: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                name: name.into(),
+                budget,
+                window: window.max(Duration::from_millis(1)),
+                usage: Mutex::new(Usage {
+                    since: Instant::now(),
+                    used: Duration::ZERO,
+                }),
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    // Poll time used so far in the current window
+    pub fn used(&self) -> Duration {
+        self.usage().used
+    }
+
+    // Current window, rolled over first if it has ended
+    fn usage(&self) -> MutexGuard<'_, Usage> {
+        let mut usage = self.inner.usage.lock().unwrap();
+        let now = Instant::now();
+        if now >= usage.since + self.inner.window {
+            usage.since = now;
+            usage.used = Duration::ZERO;
+        }
+        usage
+    }
+
+    // When the current window ends, if the budget for it is spent
+    fn throttled_until(&self) -> Option<Instant> {
+        let usage = self.usage();
+        (usage.used >= self.inner.budget).then(|| usage.since + self.inner.window)
+    }
+
+    fn charge(&self, took: Duration) {
+        self.usage().used += took;
+    }
+}
+
+pin_project! {
+    // Times every poll against the tenant's budget, and sleeps out the rest of the window
+    // instead of polling while the budget is spent
+    struct Budgeted<F> {
+        #[pin]
+        future: F,
+        tenant: Tenant,
+        throttle: Option<Sleep>,
+    }
+}
+
+impl<F: Future> Future for Budgeted<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        // a poll can only run over the budget, so this is checked before rather than after
+        while let Some(until) = this.tenant.throttled_until() {
+            let throttle = this.throttle.get_or_insert_with(|| sleep_until(until));
+            if throttle.deadline() != until {
+                throttle.reset(until);
+            }
+            if Pin::new(throttle).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+        *this.throttle = None;
+        let started = Instant::now();
+        let poll = this.future.poll(cx);
+        this.tenant.charge(started.elapsed());
+        poll
+    }
+}
+
+// Spawn `future` as one of `tenant`'s tasks, so its poll time counts against the tenant's
+// budget and it is held back with the rest of them once that is spent. It is labelled
+// `tenant=<name>`, so Metrics::label("tenant", name) covers the tenant's tasks.
+#[track_caller]
+pub fn spawn_for_tenant<F, T>(tenant: &Tenant, future: F, order: FutureType) -> Task<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let future = Budgeted {
+        future,
+        tenant: tenant.clone(),
+        throttle: None,
+    };
+    let labels = vec![("tenant".to_string(), tenant.name().to_string())];
+    crate::spawn_ordered(
+        future,
+        order,
+        SpawnOrder::Fifo,
+        None,
+        labels,
+        None,
+        Location::caller(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{Runtime, join_all};
+
+    const WINDOW: Duration = Duration::from_millis(100);
+
+    // Four tasks for `tenant` that each hold the worker for 2ms, and when the last finished
+    fn workload(tenant: &Tenant) -> Task<Instant> {
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let work = async { thread::sleep(Duration::from_millis(2)) };
+                spawn_for_tenant(tenant, work, FutureType::High)
+            })
+            .collect();
+        crate::spawn_task(
+            async move {
+                join_all(tasks).await;
+                Instant::now()
+            },
+            FutureType::High,
+        )
+    }
+
+    #[test]
+    fn over_budget_tenant_is_held_back_while_the_other_proceeds() {
+        // one worker, so a tenant's tasks can't start together before its budget is checked
+        let _runtime = runtime(Runtime::new().with_high_num(1).with_low_num(0));
+        let greedy = Tenant::new("greedy", Duration::from_millis(1), WINDOW);
+        let roomy = Tenant::new("roomy", Duration::from_secs(1), WINDOW);
+        let started = Instant::now();
+        let greedy_done = workload(&greedy);
+        let roomy_done = workload(&roomy);
+        let roomy_done = futures_lite::future::block_on(roomy_done) - started;
+        let greedy_done = futures_lite::future::block_on(greedy_done) - started;
+        // a task per window once the first one spent the budget
+        assert!(
+            greedy_done >= 3 * WINDOW,
+            "greedy done after {greedy_done:?}"
+        );
+        assert!(roomy_done < 2 * WINDOW, "roomy done after {roomy_done:?}");
+    }
+}
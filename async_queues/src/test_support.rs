@@ -0,0 +1,69 @@
+use std::process::{Command, Output, Stdio};
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{FutureType, Runtime, workers};
+
+// The worker pools and every setting are process-wide, so the unit tests that use the
+// runtime take turns: each holds this lock while it runs, with the runtime switched over to
+// the configuration it asked for.
+static LOCK: Mutex<()> = Mutex::new(());
+
+// Takes the runtime for the calling test and sets it up from `config`. Work left queued by a
+// test that failed halfway is dropped, the metrics and execution order start over, and by the
+// time this returns the workers of the previous configuration are gone.
+pub(crate) fn runtime(config: Runtime) -> MutexGuard<'static, ()> {
+    // a failed test poisons the lock, which says nothing about the next one
+    let guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    // run() waits for a task on each pool, so the first start can't be one without workers
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| Runtime::new().run());
+    drop(Runtime::drain_queued());
+    let (high, low) = (config.high_num, config.low_num);
+    Runtime::graceful_restart(config);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while workers::count(FutureType::High) != high || workers::count(FutureType::Low) != low {
+        assert!(
+            Instant::now() < deadline,
+            "workers of the previous test didn't retire, is one of its tasks still running?"
+        );
+        thread::sleep(Duration::from_millis(1));
+    }
+    #[cfg(feature = "metrics")]
+    Runtime::take_metrics();
+    Runtime::take_execution_order();
+    guard
+}
+
+// Set in a process started by `run_child` to the scenario it is meant to run
+const SCENARIO: &str = "ASYNC_QUEUES_TEST_SCENARIO";
+
+// The scenario this process was started for by `run_child`, None in a normal test run
+pub(crate) fn scenario() -> Option<String> {
+    std::env::var(SCENARIO).ok()
+}
+
+// Runs the test at `path` (e.g. "tests::spawning_without_a_runtime_uses_the_defaults") again
+// on its own in a fresh process, with `scenario` set, for what can only be seen from outside:
+// how the process exits, or what the runtime does before anything configured it. The child is
+// killed, and None returned, if it hasn't exited within `limit`.
+pub(crate) fn run_child(path: &str, scenario: &str, limit: Duration) -> Option<Output> {
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args([path, "--exact", "--nocapture", "--test-threads=1"])
+        .env(SCENARIO, scenario)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + limit;
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() >= deadline {
+            child.kill().unwrap();
+            child.wait().unwrap();
+            return None;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    Some(child.wait_with_output().unwrap())
+}
@@ -0,0 +1,80 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::task::{Context, Poll};
+
+// Ready-made futures for testing code against the runtime, behind the `test-util` feature.
+// They are the demo binary's CounterFuture and BackgroundFuture without the printing and the
+// blocking sleeps, plus a shared counter so a test can watch them make progress.
+
+// Wakes itself and returns Pending until it has been polled `polls` times, then resolves to
+// the number of polls. A future that needs several polls to finish, e.g. to check that a
+// task gets rescheduled.
+pub struct PollCountFuture {
+    target: u32,
+    polls: Arc<AtomicU32>,
+}
+
+impl PollCountFuture {
+    pub fn new(polls: u32) -> Self {
+        Self {
+            target: polls.max(1),
+            polls: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    // Polls so far, still readable after the future has been spawned
+    pub fn polls(&self) -> Arc<AtomicU32> {
+        self.polls.clone()
+    }
+}
+
+impl Future for PollCountFuture {
+    type Output = u32;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let count = self.polls.fetch_add(1, Ordering::AcqRel) + 1;
+        if count < self.target {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        } else {
+            Poll::Ready(count)
+        }
+    }
+}
+
+// Never completes: every poll wakes itself again, so the task keeps getting rescheduled until
+// its handle is dropped. Stands in for a long-running background job.
+pub struct BackgroundFuture {
+    polls: Arc<AtomicU64>,
+}
+
+impl BackgroundFuture {
+    pub fn new() -> Self {
+        Self {
+            polls: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    // Polls so far, still readable after the future has been spawned
+    pub fn polls(&self) -> Arc<AtomicU64> {
+        self.polls.clone()
+    }
+}
+
+impl Default for BackgroundFuture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Future for BackgroundFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.polls.fetch_add(1, Ordering::AcqRel);
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
@@ -0,0 +1,385 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, LazyLock, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pin_project_lite::pin_project;
+
+// A single background thread owns every pending deadline. Unlike the commented out AsyncSleep
+// in the demo, a sleeping future doesn't wake itself in a loop; it hands its waker to the timer
+// thread and stays off the queues until the deadline passes.
+struct Timer {
+    state: Mutex<TimerState>,
+    changed: Condvar,
+    // deadlines are rounded up onto a grid of `resolution` steps counted from here
+    epoch: Instant,
+}
+
+// Granularity of the timer thread, set through Runtime::with_timer_resolution. A coarser grid
+// lets nearby deadlines share one wake-up of the timer thread, a finer one fires closer to the
+// requested instant. Defaults to 1ms.
+pub(crate) const DEFAULT_RESOLUTION: Duration = Duration::from_millis(1);
+static RESOLUTION_NANOS: AtomicU64 = AtomicU64::new(DEFAULT_RESOLUTION.as_nanos() as u64);
+
+pub(crate) fn set_resolution(resolution: Duration) {
+    RESOLUTION_NANOS.store(resolution.as_nanos() as u64, Ordering::Relaxed);
+}
+
+struct TimerState {
+    deadlines: BinaryHeap<Reverse<(Instant, u64)>>,
+    wakers: HashMap<u64, Waker>,
+    next_id: u64,
+}
+
+static TIMER: LazyLock<Timer> = LazyLock::new(|| {
+    // the thread blocks on TIMER until this initializer has returned
+    thread::Builder::new()
+        .name("async-queues-timer".into())
+        .spawn(|| TIMER.run())
+        .expect("failed to spawn the timer thread");
+    Timer {
+        state: Mutex::new(TimerState {
+            deadlines: BinaryHeap::new(),
+            wakers: HashMap::new(),
+            next_id: 0,
+        }),
+        changed: Condvar::new(),
+        epoch: Instant::now(),
+    }
+});
+
+// Starts the timer thread if no sleep has done so yet
+pub(crate) fn start() {
+    LazyLock::force(&TIMER);
+}
+
+impl Timer {
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let mut due = Vec::new();
+            while let Some(Reverse((deadline, id))) = state.deadlines.peek().copied() {
+                if deadline > now {
+                    break;
+                }
+                state.deadlines.pop();
+                // entries whose Sleep was dropped have no waker left, skip them
+                if let Some(waker) = state.wakers.remove(&id) {
+                    due.push(waker);
+                }
+            }
+            // wake outside the lock, a woken task may well register its next deadline
+            if !due.is_empty() {
+                drop(state);
+                due.into_iter().for_each(Waker::wake);
+                state = self.state.lock().unwrap();
+                continue;
+            }
+            state = match state.deadlines.peek() {
+                Some(Reverse((deadline, _))) => {
+                    let wait = deadline.saturating_duration_since(now);
+                    self.changed.wait_timeout(state, wait).unwrap().0
+                }
+                None => self.changed.wait(state).unwrap(),
+            };
+        }
+    }
+
+    // Anything shorter than the resolution rounds up to the next tick rather than being lost
+    fn round_up(&self, deadline: Instant) -> Instant {
+        let resolution = RESOLUTION_NANOS.load(Ordering::Relaxed) as u128;
+        if resolution == 0 {
+            return deadline;
+        }
+        let since_epoch = deadline.saturating_duration_since(self.epoch).as_nanos();
+        let ticks = since_epoch.div_ceil(resolution);
+        self.epoch + Duration::from_nanos((ticks * resolution) as u64)
+    }
+
+    fn register(&self, deadline: Instant, waker: &Waker) -> u64 {
+        let deadline = self.round_up(deadline);
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.deadlines.push(Reverse((deadline, id)));
+        state.wakers.insert(id, waker.clone());
+        self.changed.notify_one();
+        id
+    }
+
+    // true if the entry was still waiting and its waker got swapped
+    fn refresh(&self, id: u64, waker: &Waker) -> bool {
+        match self.state.lock().unwrap().wakers.get_mut(&id) {
+            Some(stored) => {
+                if !stored.will_wake(waker) {
+                    *stored = waker.clone();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn cancel(&self, id: u64) {
+        self.state.lock().unwrap().wakers.remove(&id);
+    }
+}
+
+// Resolves once the deadline has passed
+pub struct Sleep {
+    deadline: Instant,
+    entry: Option<u64>,
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        entry: None,
+    }
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    // move the deadline, e.g. for idle timeouts that get pushed back on activity
+    pub fn reset(&mut self, deadline: Instant) {
+        if let Some(id) = self.entry.take() {
+            TIMER.cancel(id);
+        }
+        self.deadline = deadline;
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            if let Some(id) = self.entry.take() {
+                TIMER.cancel(id);
+            }
+            return Poll::Ready(());
+        }
+        if let Some(id) = self.entry
+            && TIMER.refresh(id, cx.waker())
+        {
+            return Poll::Pending;
+        }
+        self.entry = Some(TIMER.register(self.deadline, cx.waker()));
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(id) = self.entry {
+            TIMER.cancel(id);
+        }
+    }
+}
+
+// Returned when a timeout fires before the future it guards finished
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+pin_project! {
+    pub struct Timeout<F> {
+        #[pin]
+        future: F,
+        sleep: Sleep,
+    }
+}
+
+// Drive `future` but give up with Err(Elapsed) once `duration` has passed.
+// The future is dropped when the timeout fires.
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep(duration),
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(value) = this.future.poll(cx) {
+            return Poll::Ready(Ok(value));
+        }
+        match Pin::new(this.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Elapsed)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// Like `timeout`, but when `duration` runs out the primary is dropped and the future built by
+// `fallback` is driven to completion instead, e.g. to answer from a cache when the fresh
+// lookup is too slow. The fallback itself has no deadline.
+pub async fn timeout_or_else<F, G, Fb>(duration: Duration, primary: F, fallback: Fb) -> F::Output
+where
+    F: Future,
+    Fb: FnOnce() -> G,
+    G: Future<Output = F::Output>,
+{
+    match timeout(duration, primary).await {
+        Ok(output) => output,
+        Err(Elapsed) => fallback().await,
+    }
+}
+
+pin_project! {
+    pub struct Heartbeat<F, H> {
+        #[pin]
+        future: F,
+        period: Duration,
+        beat: H,
+        sleep: Sleep,
+    }
+}
+
+// Drive `future` and call `beat` every `period` until it completes, e.g. to report liveness
+// to a watchdog or a UI during a long operation. The timer wakes the combinator for every
+// beat, so beats keep coming while the future is waiting on something else; one that blocks
+// its thread holds them up though. Beats missed meanwhile aren't made up for: after a late
+// beat the next one is a full period away. Periods under 1ms are taken as 1ms.
+pub fn with_heartbeat<F, H>(future: F, period: Duration, beat: H) -> Heartbeat<F, H>
+where
+    F: Future,
+    H: FnMut(),
+{
+    let period = period.max(Duration::from_millis(1));
+    Heartbeat {
+        future,
+        period,
+        beat,
+        sleep: sleep(period),
+    }
+}
+
+impl<F: Future, H: FnMut()> Future for Heartbeat<F, H> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(value) = this.future.poll(cx) {
+            return Poll::Ready(value);
+        }
+        while Pin::new(&mut *this.sleep).poll(cx).is_ready() {
+            (this.beat)();
+            // keep to the schedule if on time, start a new one from now if late
+            let now = Instant::now();
+            let mut next = this.sleep.deadline() + *this.period;
+            if next <= now {
+                next = now + *this.period;
+            }
+            this.sleep.reset(next);
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{FutureType, Runtime, join_all, spawn_task};
+
+    #[test]
+    fn sleeps_shorter_than_the_resolution_round_up() {
+        let _runtime = runtime(Runtime::new().with_timer_resolution(Duration::from_millis(50)));
+        let started = Instant::now();
+        let sleeps: Vec<_> = [1, 500, 3_000]
+            .into_iter()
+            .map(|micros| spawn_task(sleep(Duration::from_micros(micros)), FutureType::High))
+            .collect();
+        futures_lite::future::block_on(join_all(sleeps));
+        // fired by the tick after the deadline, not lost for being too short
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let deadline = Instant::now() + Duration::from_millis(1);
+        let rounded = TIMER.round_up(deadline);
+        assert!(rounded >= deadline);
+        assert!(rounded - deadline < Duration::from_millis(50));
+        assert_eq!(
+            rounded.duration_since(TIMER.epoch).as_nanos() % 50_000_000,
+            0
+        );
+    }
+
+    #[test]
+    fn timeout_or_else_falls_back_when_the_primary_is_slow() {
+        let _runtime = runtime(Runtime::new());
+        let slow = async {
+            sleep(Duration::from_secs(5)).await;
+            "fresh"
+        };
+        let started = Instant::now();
+        let value = futures_lite::future::block_on(timeout_or_else(
+            Duration::from_millis(20),
+            slow,
+            || async { "cached" },
+        ));
+        assert_eq!(value, "cached");
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let fast = async { "fresh" };
+        let value = futures_lite::future::block_on(timeout_or_else(
+            Duration::from_secs(5),
+            fast,
+            || async { unreachable!("the primary finished in time") },
+        ));
+        assert_eq!(value, "fresh");
+    }
+
+    #[test]
+    fn heartbeat_fires_every_period_until_the_future_is_done() {
+        let _runtime = runtime(Runtime::new());
+        let mut beats = 0;
+        let started = Instant::now();
+        let value = futures_lite::future::block_on(with_heartbeat(
+            async {
+                sleep(Duration::from_secs(1)).await;
+                "done"
+            },
+            Duration::from_millis(200),
+            || beats += 1,
+        ));
+        assert_eq!(value, "done");
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        // the fifth is due the moment the future finishes, so it may not get to fire
+        assert!((4..=5).contains(&beats), "{beats} beats");
+
+        // a zero period beats once a millisecond rather than spinning
+        let mut beats = 0;
+        futures_lite::future::block_on(with_heartbeat(
+            sleep(Duration::from_millis(20)),
+            Duration::ZERO,
+            || beats += 1,
+        ));
+        assert!(beats <= 25, "{beats} beats");
+    }
+}
@@ -0,0 +1,214 @@
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use pin_project_lite::pin_project;
+
+use crate::{TaskId, TaskRecord};
+
+// Polls longer than this are treated as blocking the worker, set through
+// Runtime::with_slow_poll_watchdog. 0 means the watchdog is off and polls aren't timed at all.
+static SLOW_POLL_NANOS: AtomicU64 = AtomicU64::new(0);
+
+// Aggregated per spawn site, since every instance spawned from the same line tends to block
+// the same way
+static SITES: Mutex<Vec<BlockingDiagnostic>> = Mutex::new(Vec::new());
+
+// What the watchdog knows about a spawn site whose tasks block their worker
+#[derive(Clone, Debug)]
+pub struct BlockingDiagnostic {
+    pub location: &'static Location<'static>,
+    // the task that tripped the watchdog first
+    pub first_task: TaskId,
+    pub slow_polls: u64,
+    pub longest_poll: Duration,
+}
+
+pub(crate) fn set_threshold(threshold: Option<Duration>) {
+    let nanos = threshold.map_or(0, |t| t.as_nanos().max(1) as u64);
+    SLOW_POLL_NANOS.store(nanos, Ordering::Relaxed);
+}
+
+#[cfg(any(feature = "metrics", feature = "task-registry"))]
+pub(crate) fn threshold() -> Option<Duration> {
+    let nanos = SLOW_POLL_NANOS.load(Ordering::Relaxed);
+    (nanos != 0).then(|| Duration::from_nanos(nanos))
+}
+
+// Start of a timed poll, None while the watchdog is off
+pub(crate) fn poll_started() -> Option<Instant> {
+    (SLOW_POLL_NANOS.load(Ordering::Relaxed) != 0).then(Instant::now)
+}
+
+pub(crate) fn poll_finished(record: &TaskRecord, started: Option<Instant>) {
+    let Some(started) = started else {
+        return;
+    };
+    let took = started.elapsed();
+    let threshold = Duration::from_nanos(SLOW_POLL_NANOS.load(Ordering::Relaxed));
+    if threshold.is_zero() || took < threshold {
+        return;
+    }
+
+    let location = record.location();
+    let mut sites = SITES.lock().unwrap();
+    if let Some(site) = sites.iter_mut().find(|s| s.location == location) {
+        site.slow_polls += 1;
+        site.longest_poll = site.longest_poll.max(took);
+        return;
+    }
+    // first time this site blocks: say so once, with enough to find the offending spawn
+    eprintln!(
+        "async_queues: {} spawned at {} blocked its worker for {:?} in a single poll; \
+         it is most likely doing blocking work (std::thread::sleep, sync IO) and should run \
+         on a dedicated thread instead",
+        record.id(),
+        location,
+        took
+    );
+    sites.push(BlockingDiagnostic {
+        location,
+        first_task: record.id(),
+        slow_polls: 1,
+        longest_poll: took,
+    });
+}
+
+pub(crate) fn diagnostics() -> Vec<BlockingDiagnostic> {
+    SITES.lock().unwrap().clone()
+}
+
+// Returned by poll_timeout when a single poll ran over its limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stalled {
+    // how long the offending poll took
+    pub took: Duration,
+}
+
+impl fmt::Display for Stalled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a single poll took {:?}", self.took)
+    }
+}
+
+impl Error for Stalled {}
+
+pin_project! {
+    pub struct PollTimeout<F> {
+        #[pin]
+        future: F,
+        limit: Duration,
+    }
+}
+
+// Drive `future` but fail with Err(Stalled) as soon as one of its polls takes longer than
+// `per_poll`, however quickly it would finish overall. A poll can't be interrupted, so this
+// fires after the blocking poll returns; the future is dropped then. It is the per-future
+// counterpart of Runtime::with_slow_poll_watchdog, and a task that stalls this way still shows
+// up in Runtime::blocking_diagnostics when the watchdog is on.
+pub fn poll_timeout<F: Future>(future: F, per_poll: Duration) -> PollTimeout<F> {
+    PollTimeout {
+        future,
+        limit: per_poll,
+    }
+}
+
+impl<F: Future> Future for PollTimeout<F> {
+    type Output = Result<F::Output, Stalled>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let started = Instant::now();
+        let poll = this.future.poll(cx);
+        let took = started.elapsed();
+        if took > *this.limit {
+            return Poll::Ready(Err(Stalled { took }));
+        }
+        poll.map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{FutureType, Runtime, spawn_task};
+
+    // the demo binary's async_fn: an async fn doing blocking work
+    async fn async_fn() {
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    #[test]
+    fn blocking_poll_is_reported_with_its_spawn_site() {
+        let _runtime = runtime(Runtime::new().with_slow_poll_watchdog(Duration::from_millis(50)));
+        let line = line!() + 1;
+        let task = spawn_task(async_fn(), FutureType::Low);
+        let id = task.metadata().id();
+        futures_lite::future::block_on(task);
+
+        let diagnostics = Runtime::blocking_diagnostics();
+        let site = diagnostics
+            .iter()
+            .find(|site| site.location.file() == file!() && site.location.line() == line)
+            .expect("the blocking spawn site wasn't reported");
+        assert_eq!(site.first_task, id);
+        assert_eq!(site.slow_polls, 1);
+        assert!(site.longest_poll >= Duration::from_millis(100));
+    }
+
+    // Blocks its thread for `per_poll` in each of its three polls
+    struct SleepyFuture {
+        per_poll: Duration,
+        polls: u32,
+    }
+
+    impl Future for SleepyFuture {
+        type Output = u32;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            thread::sleep(self.per_poll);
+            self.polls += 1;
+            if self.polls < 3 {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(self.polls)
+            }
+        }
+    }
+
+    #[test]
+    fn poll_over_the_limit_fails_with_stalled() {
+        let _runtime = runtime(Runtime::new());
+        let sleepy = SleepyFuture {
+            per_poll: Duration::from_secs(1),
+            polls: 0,
+        };
+        let task = spawn_task(
+            poll_timeout(sleepy, Duration::from_millis(100)),
+            FutureType::High,
+        );
+        let stalled = futures_lite::future::block_on(task).unwrap_err();
+        assert!(stalled.took >= Duration::from_secs(1));
+
+        // quick polls get through however many there are
+        let quick = SleepyFuture {
+            per_poll: Duration::ZERO,
+            polls: 0,
+        };
+        let task = spawn_task(
+            poll_timeout(quick, Duration::from_millis(100)),
+            FutureType::High,
+        );
+        assert_eq!(futures_lite::future::block_on(task), Ok(3));
+    }
+}
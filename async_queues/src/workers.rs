@@ -0,0 +1,195 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::panic::Location;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::task::{Poll, Waker};
+
+use futures_lite::future;
+
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::{FutureType, HIGHQUEUE, LOWQUEUE, Task, TaskRunnable, continuation, queued};
+
+// Worker threads running, per pool and overall, and how many of them are polling a task right
+// now. Indices keep counting up across graceful restarts, so a retired worker's isn't reused.
+static NEXT_WORKER: AtomicUsize = AtomicUsize::new(0);
+static WORKERS: AtomicUsize = AtomicUsize::new(0);
+static HIGH_WORKERS: AtomicUsize = AtomicUsize::new(0);
+static LOW_WORKERS: AtomicUsize = AtomicUsize::new(0);
+static BUSY: AtomicUsize = AtomicUsize::new(0);
+// Producers parked in spawn_when_capacity, woken whenever a worker frees up. The count
+// mirrors the list's length so a worker finishing a poll only takes the lock if somebody is
+// actually waiting.
+static CAPACITY_WAITERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+static CAPACITY_WAITING: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // set while this thread is a worker in the middle of polling a task
+    static ON_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+fn pool_counter(pool: FutureType) -> &'static AtomicUsize {
+    match pool {
+        FutureType::High => &HIGH_WORKERS,
+        FutureType::Low => &LOW_WORKERS,
+    }
+}
+
+// Registers a new worker thread of the given pool and returns its index
+pub(crate) fn started(pool: FutureType) -> usize {
+    pool_counter(pool).fetch_add(1, Ordering::Relaxed);
+    WORKERS.fetch_add(1, Ordering::Relaxed);
+    NEXT_WORKER.fetch_add(1, Ordering::Relaxed)
+}
+
+// Held by a worker thread while it runs, and unregisters it on the way out, whether it retired
+// after a graceful restart or a task panic under PanicPolicy::Propagate unwound it
+pub(crate) struct Exit {
+    pub(crate) pool: FutureType,
+    #[cfg(feature = "metrics")]
+    pub(crate) worker: usize,
+}
+
+impl Drop for Exit {
+    fn drop(&mut self) {
+        pool_counter(self.pool).fetch_sub(1, Ordering::Relaxed);
+        WORKERS.fetch_sub(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        metrics::unregister(self.worker);
+    }
+}
+
+pub(crate) fn count(pool: FutureType) -> usize {
+    pool_counter(pool).load(Ordering::Relaxed)
+}
+
+// Tasks being polled by a worker right now
+pub(crate) fn in_flight() -> usize {
+    BUSY.load(Ordering::Acquire)
+}
+
+// Decrements on drop so a panicking task still gives its worker back
+struct BusyGuard;
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        ON_WORKER.set(false);
+        // SeqCst pairs with spawn_when_capacity: either it sees this worker free or we see it
+        // waiting
+        BUSY.fetch_sub(1, Ordering::SeqCst);
+        if CAPACITY_WAITING.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let wakers = {
+            let mut waiters = CAPACITY_WAITERS.lock().unwrap();
+            CAPACITY_WAITING.store(0, Ordering::SeqCst);
+            std::mem::take(&mut *waiters)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+// Whether this thread is a worker polling a task, i.e. whether the caller runs inside one
+pub(crate) fn on_worker() -> bool {
+    ON_WORKER.get()
+}
+
+// Every worker loop goes through here to poll a task
+pub(crate) fn run(runnable: TaskRunnable) {
+    BUSY.fetch_add(1, Ordering::AcqRel);
+    ON_WORKER.set(true);
+    let _busy = BusyGuard;
+    #[cfg(feature = "metrics")]
+    let _span = metrics::busy();
+    let _polling = continuation::polling(runnable.metadata().id());
+    runnable.run();
+}
+
+// A worker is free when it isn't polling anything and there's no queued task already
+// lined up for it. A producer running on a worker doesn't count its own worker as busy,
+// it hands that worker back as soon as it returns.
+fn has_capacity() -> bool {
+    let queued = queued(FutureType::High) + queued(FutureType::Low);
+    let busy = BUSY.load(Ordering::SeqCst) - ON_WORKER.get() as usize;
+    busy + queued < WORKERS.load(Ordering::Relaxed)
+}
+
+// Spawn only once a worker is idle, so a fast producer paces itself to what the workers
+// can actually get through instead of piling up a long queue. Several producers racing for
+// the same idle worker can overshoot by a task each; the queue stays around the worker count.
+// resolves to the Task itself, awaiting that is up to the caller
+#[allow(clippy::async_yields_async)]
+#[track_caller]
+pub fn spawn_when_capacity<F, T>(future: F, order: FutureType) -> impl Future<Output = Task<T>>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let location = Location::caller();
+    async move {
+        // make sure the pools exist, otherwise there is never any capacity to wait for
+        LazyLock::force(&HIGHQUEUE);
+        LazyLock::force(&LOWQUEUE);
+        future::poll_fn(|cx| {
+            if has_capacity() {
+                return Poll::Ready(());
+            }
+            let mut waiters = CAPACITY_WAITERS.lock().unwrap();
+            if !waiters.iter().any(|waker| waker.will_wake(cx.waker())) {
+                waiters.push(cx.waker().clone());
+            }
+            CAPACITY_WAITING.store(waiters.len(), Ordering::SeqCst);
+            drop(waiters);
+            // a worker may have freed up before the waker was stored
+            if has_capacity() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        crate::spawn(future, order, None, location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_support::runtime;
+    use crate::{Runtime, join_all};
+
+    const PRODUCERS: usize = 4;
+
+    #[test]
+    fn producers_wait_for_the_only_worker() {
+        let _runtime = runtime(Runtime::new().with_high_num(1).with_low_num(0));
+        // the longest the queue got, as seen right after each spawn
+        static MOST: AtomicUsize = AtomicUsize::new(0);
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|_| {
+                thread::spawn(|| {
+                    future::block_on(async {
+                        let mut tasks = Vec::new();
+                        for _ in 0..10 {
+                            let work = async { thread::sleep(Duration::from_millis(1)) };
+                            tasks.push(spawn_when_capacity(work, FutureType::High).await);
+                            MOST.fetch_max(queued(FutureType::High), Ordering::Relaxed);
+                        }
+                        join_all(tasks).await;
+                    })
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        // every producer may overshoot by a task when they race for the worker, no more
+        let most = MOST.load(Ordering::Relaxed);
+        assert!(most <= PRODUCERS, "queue reached {most}");
+    }
+}
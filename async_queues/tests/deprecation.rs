@@ -0,0 +1,7 @@
+// The one-argument spawn_task! form is deprecated at the call site, the two-argument one isn't
+#[test]
+fn implicit_low_priority_is_deprecated() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/spawn_task_implicit_low.rs");
+    cases.pass("tests/ui/spawn_task_explicit_priority.rs");
+}
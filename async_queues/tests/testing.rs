@@ -0,0 +1,31 @@
+// Uses the test-util futures the way a downstream crate would, from outside the library
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use async_queues::testing::{BackgroundFuture, PollCountFuture};
+use async_queues::{FutureType, Runtime, spawn_task};
+
+#[test]
+fn poll_count_and_background_futures() {
+    let runtime = Runtime::new();
+    runtime.run();
+
+    let counter = PollCountFuture::new(3);
+    let polls = counter.polls();
+    let task = spawn_task(counter, FutureType::High);
+    assert_eq!(runtime.block_on(task), 3);
+    assert_eq!(polls.load(Ordering::Acquire), 3);
+
+    let background = BackgroundFuture::new();
+    let polls = background.polls();
+    let task = spawn_task(background, FutureType::Low);
+    while polls.load(Ordering::Acquire) < 10 {
+        thread::yield_now();
+    }
+    // it never finishes on its own; cancelling is what stops it
+    runtime.block_on(task.cancel());
+    let stopped_at = polls.load(Ordering::Acquire);
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(polls.load(Ordering::Acquire), stopped_at);
+}
@@ -0,0 +1,7 @@
+#![deny(deprecated)]
+
+use async_queues::{FutureType, spawn_task};
+
+fn main() {
+    let _spawn = || spawn_task!(async {}, FutureType::Low);
+}